@@ -1,6 +1,17 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
-use anyhow::Result;
+use anyhow::{Result, bail};
+
+use crate::api::auth::Scope;
+use crate::inference::admission::Priority;
+use crate::model::catalog::is_valid_repo_id;
+use crate::preprocessing::normalizer::UnicodeForm;
+use crate::preprocessing::chain::{self, PreprocessingStep};
+use crate::preprocessing::chat_template::{ChatTemplate, ChatTemplateSource};
+use crate::preprocessing::language::LanguageConfig;
+use crate::preprocessing::redaction::RedactionConfig;
+use crate::preprocessing::truncation::TruncationConfig;
 
 pub mod settings;
 
@@ -13,6 +24,10 @@ pub struct AppConfig {
     pub monitoring: MonitoringConfig,
     pub cache: CacheConfig,
     pub performance: PerformanceConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +36,13 @@ pub struct ServerConfig {
     pub port: u16,
     pub workers: usize,
     pub request_timeout_ms: u64,
+    /// Longest timeout a client may ask for with `x-request-timeout-ms`.
+    /// Unset means clients can only shorten `request_timeout_ms`
+    #[serde(default)]
+    pub max_request_timeout_ms: Option<u64>,
+    /// Origins allowed by CORS; empty allows any
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +50,9 @@ pub struct ModelsConfig {
     pub default: String,
     pub cache_dir: PathBuf,
     pub auto_download: bool,
+    /// Lets clients load hub repos that aren't in `available_models`
+    #[serde(default)]
+    pub allow_unlisted_models: bool,
     pub available_models: Vec<ModelInfo>,
 }
 
@@ -36,6 +61,24 @@ pub struct ModelInfo {
     pub name: String,
     pub task: String,
     pub repo: String,
+    /// Branch, tag or commit on the hub; defaults to "main"
+    #[serde(default)]
+    pub revision: Option<String>,
+    /// Name of an entry in `preprocessing.pipelines`
+    #[serde(default)]
+    pub preprocessing: Option<String>,
+    /// Jinja chat template overriding the one in tokenizer_config.json
+    #[serde(default)]
+    pub chat_template: Option<String>,
+}
+
+impl ModelInfo {
+    pub fn is_classification(&self) -> bool {
+        matches!(
+            self.task.as_str(),
+            "sentiment-analysis" | "text-classification" | "classification"
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +88,16 @@ pub struct InferenceConfig {
     pub device: String,
     pub num_threads: usize,
     pub enable_gpu: bool,
+    #[serde(default)]
+    pub truncation: TruncationConfig,
+    /// Most labels one zero-shot request may score; each label is a
+    /// forward pass
+    #[serde(default = "default_max_candidate_labels")]
+    pub max_candidate_labels: usize,
+}
+
+fn default_max_candidate_labels() -> usize {
+    32
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,15 +105,153 @@ pub struct PreprocessingConfig {
     pub lowercase: bool,
     pub remove_special_chars: bool,
     pub max_input_length: usize,
+    #[serde(default)]
+    pub unicode_normalization: UnicodeForm,
+    #[serde(default)]
+    pub strip_accents: bool,
+    #[serde(default)]
+    pub fold_full_width: bool,
+    #[serde(default)]
+    pub remove_control_chars: bool,
+    #[serde(default)]
+    pub remove_zero_width_chars: bool,
+    /// Named, ordered step lists that models can opt into
+    #[serde(default)]
+    pub pipelines: HashMap<String, Vec<PreprocessingStep>>,
+    /// Pipeline for models that don't name one; the flags above apply otherwise
+    #[serde(default)]
+    pub default_pipeline: Option<String>,
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    #[serde(default)]
+    pub language: LanguageConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MonitoringConfig {
     pub enable_metrics: bool,
+    /// Interface the scrape endpoint binds; it has no authentication, so
+    /// it stays on loopback unless set
+    #[serde(default = "default_metrics_host")]
+    pub metrics_host: String,
     pub metrics_port: u16,
+    /// Initial filter, e.g. `info` or `info,transformer_forge::api=debug`;
+    /// can be changed at runtime through `/admin/log-level`
     pub log_level: String,
-    pub log_format: String,
+    /// Format of both sinks unless overridden below
+    pub log_format: LogFormat,
     pub log_file: PathBuf,
+    #[serde(default)]
+    pub stdout_format: Option<LogFormat>,
+    #[serde(default)]
+    pub file_format: Option<LogFormat>,
+    /// Log to stdout only, for containers that collect it
+    #[serde(default)]
+    pub stdout_only: bool,
+    #[serde(default)]
+    pub log_rotation: LogRotation,
+    /// File size that triggers `size` rotation
+    #[serde(default = "default_log_max_size_mb")]
+    pub log_max_size_mb: u64,
+    /// Log files kept, the current one included
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: usize,
+    /// Span export to an OpenTelemetry collector
+    #[serde(default)]
+    pub otlp: OtlpConfig,
+}
+
+impl MonitoringConfig {
+    pub fn stdout_format(&self) -> LogFormat {
+        self.stdout_format.unwrap_or(self.log_format)
+    }
+
+    pub fn file_format(&self) -> LogFormat {
+        self.file_format.unwrap_or(self.log_format)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Json,
+    Pretty,
+    /// One line per event
+    Compact,
+    /// `key=value` pairs
+    Logfmt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    /// When the file reaches `log_max_size_mb`
+    Size,
+    Never,
+}
+
+fn default_metrics_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_log_max_size_mb() -> u64 {
+    100
+}
+
+fn default_log_max_files() -> usize {
+    7
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OtlpConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Collector address; `/v1/traces` is appended for HTTP
+    #[serde(default = "default_otlp_endpoint")]
+    pub endpoint: String,
+    #[serde(default)]
+    pub protocol: OtlpProtocol,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Share of new traces exported, 0.0 to 1.0. Requests arriving with a
+    /// `traceparent` follow the caller's sampling decision
+    #[serde(default = "default_sampling_ratio")]
+    pub sampling_ratio: f64,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: default_otlp_endpoint(),
+            protocol: OtlpProtocol::default(),
+            service_name: default_service_name(),
+            sampling_ratio: default_sampling_ratio(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpProtocol {
+    #[default]
+    Grpc,
+    Http,
+}
+
+fn default_otlp_endpoint() -> String {
+    "http://localhost:4317".to_string()
+}
+
+fn default_service_name() -> String {
+    "transformer-forge".to_string()
+}
+
+fn default_sampling_ratio() -> f64 {
+    1.0
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,6 +261,103 @@ pub struct CacheConfig {
     pub max_entries: usize,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthConfig {
+    /// When off every route is open, as before keys existed
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub keys: Vec<ApiKeyConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyConfig {
+    /// Shown in logs in place of the key
+    pub id: String,
+    /// Hex SHA-256 of the secret; the secret itself is never stored
+    pub key_hash: String,
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
+    /// Models this key may use; empty allows all
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Admission class when the request doesn't send `x-priority`
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// Overrides `rate_limit.per_key` for this key
+    #[serde(default)]
+    pub rate_limits: Option<RateLimits>,
+    /// Overrides `rate_limit.quotas` for this key
+    #[serde(default)]
+    pub quotas: Option<QuotaConfig>,
+}
+
+impl ApiKeyConfig {
+    pub fn allows_model(&self, model: &str) -> bool {
+        self.models.is_empty() || self.models.iter().any(|allowed| allowed == model)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Buckets for each API key
+    #[serde(default)]
+    pub per_key: RateLimits,
+    /// Buckets for each client IP, with or without a key
+    #[serde(default)]
+    pub per_ip: RateLimits,
+    #[serde(default)]
+    pub quotas: QuotaConfig,
+    /// Where quota usage is persisted between restarts
+    #[serde(default = "default_usage_file")]
+    pub usage_file: PathBuf,
+    /// Take the client IP from `x-forwarded-for`; only behind a proxy
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            per_key: RateLimits::default(),
+            per_ip: RateLimits::default(),
+            quotas: QuotaConfig::default(),
+            usage_file: default_usage_file(),
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+fn default_usage_file() -> PathBuf {
+    PathBuf::from("./usage.json")
+}
+
+/// Token-bucket sizes; unset means unlimited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RateLimits {
+    #[serde(default)]
+    pub requests_per_minute: Option<u64>,
+    /// Input tokens, counted with the target model's tokenizer
+    #[serde(default)]
+    pub tokens_per_minute: Option<u64>,
+}
+
+/// Per-key totals for the UTC day and month; unset means unlimited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    #[serde(default)]
+    pub daily_requests: Option<u64>,
+    #[serde(default)]
+    pub daily_tokens: Option<u64>,
+    #[serde(default)]
+    pub monthly_requests: Option<u64>,
+    #[serde(default)]
+    pub monthly_tokens: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PerformanceConfig {
     pub async_workers: usize,
@@ -87,7 +375,107 @@ impl AppConfig {
             .build()?;
         
         let app_config: AppConfig = config.try_deserialize()?;
+        app_config.validate()?;
         
         Ok(app_config)
     }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.server.request_timeout_ms == 0 {
+            bail!("server.request_timeout_ms must be greater than 0");
+        }
+        if self.server.max_request_timeout_ms.is_some_and(|max| max < self.server.request_timeout_ms) {
+            bail!("server.max_request_timeout_ms is lower than request_timeout_ms");
+        }
+        
+        let inference = &self.inference;
+        if inference.max_length == 0 {
+            bail!("inference.max_length must be greater than 0");
+        }
+        // Windows advance by max_length - stride tokens
+        if inference.truncation.stride >= inference.max_length {
+            bail!(
+                "inference.truncation.stride ({}) must be smaller than inference.max_length ({})",
+                inference.truncation.stride,
+                inference.max_length
+            );
+        }
+        if inference.truncation.max_windows == 0 {
+            bail!("inference.truncation.max_windows must be at least 1");
+        }
+        if inference.max_candidate_labels == 0 {
+            bail!("inference.max_candidate_labels must be at least 1");
+        }
+        
+        if self.monitoring.log_max_files == 0 {
+            bail!("monitoring.log_max_files must be at least 1");
+        }
+        if self.monitoring.log_rotation == LogRotation::Size && self.monitoring.log_max_size_mb == 0 {
+            bail!("monitoring.log_max_size_mb must be greater than 0 for size rotation");
+        }
+        
+        let otlp = &self.monitoring.otlp;
+        if !(0.0..=1.0).contains(&otlp.sampling_ratio) {
+            bail!("monitoring.otlp.sampling_ratio must be between 0.0 and 1.0");
+        }
+        if otlp.enabled && otlp.endpoint.trim().is_empty() {
+            bail!("monitoring.otlp.endpoint is required when OTLP export is enabled");
+        }
+        
+        let mut key_ids = std::collections::HashSet::new();
+        for key in &self.auth.keys {
+            if !key_ids.insert(key.id.as_str()) {
+                bail!("API key id '{}' is used twice", key.id);
+            }
+        }
+        
+        let mut names = std::collections::HashSet::new();
+        for model in &self.models.available_models {
+            if !names.insert(model.name.as_str()) {
+                bail!("Model '{}' is listed twice in available_models", model.name);
+            }
+            if !is_valid_repo_id(&model.repo) {
+                bail!("Model '{}' has an invalid repo id '{}'", model.name, model.repo);
+            }
+            if let Some(template) = &model.chat_template {
+                if let Err(e) = ChatTemplate::new(template.clone(), ChatTemplateSource::Config, None, None) {
+                    bail!("Model '{}': {}", model.name, e);
+                }
+            }
+        }
+        
+        if self.model_info(&self.models.default).is_none() && !self.models.allow_unlisted_models {
+            bail!("Default model '{}' is not in available_models", self.models.default);
+        }
+        
+        for (name, steps) in &self.preprocessing.pipelines {
+            chain::validate_chain(name, steps)?;
+        }
+        
+        let referenced = self.models.available_models
+            .iter()
+            .filter_map(|m| m.preprocessing.as_ref().map(|p| (m.name.as_str(), p)))
+            .chain(self.preprocessing.default_pipeline.as_ref().map(|p| ("default_pipeline", p)));
+        
+        for (owner, pipeline) in referenced {
+            if !self.preprocessing.pipelines.contains_key(pipeline) {
+                bail!("{} references unknown preprocessing pipeline '{}'", owner, pipeline);
+            }
+        }
+        
+        let routing = &self.preprocessing.language.routing;
+        if routing.enabled {
+            for model in routing.rules.values().chain(routing.fallback.iter()) {
+                if self.model_info(model).is_none() {
+                    bail!("Language routing targets unknown model '{}'", model);
+                }
+            }
+        }
+        
+        Ok(())
+    }
+
+    pub fn model_info(&self, name: &str) -> Option<&ModelInfo> {
+        self.models.available_models.iter().find(|m| m.name == name)
+    }
 }
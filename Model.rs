@@ -2,19 +2,39 @@ use anyhow::Result;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tracing::Instrument;
 use dashmap::DashMap;
 
 pub mod loader;
 pub mod cache;
 pub mod registry;
+pub mod catalog;
 
 use crate::config::AppConfig;
+use crate::preprocessing::chat_template::{ChatTemplate, ChatTemplateSource};
+use crate::preprocessing::tokenizer::CustomTokenizer;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ModelError {
+    #[error("Unknown model '{0}'; it is not in models.available_models")]
+    NotFound(String),
+    #[error("Invalid model name '{0}'")]
+    InvalidName(String),
+    #[error("No active model")]
+    NoActiveModel,
+    #[error("Failed to load model {model}: {reason}")]
+    Loading { model: String, reason: String },
+}
 
 #[derive(Debug, Clone)]
 pub struct ModelManager {
     pub registry: Arc<registry::ModelRegistry>,
     pub cache: Arc<cache::ModelCache>,
     pub config: Arc<AppConfig>,
+    pub catalog: Arc<catalog::ModelCatalog>,
+    tokenizers: Arc<DashMap<String, Arc<CustomTokenizer>>>,
+    /// Compiled chat templates of loaded models
+    chat_templates: Arc<DashMap<String, Option<Arc<ChatTemplate>>>>,
 }
 
 impl ModelManager {
@@ -24,11 +44,15 @@ impl ModelManager {
             config.cache.max_entries,
             config.cache.ttl_seconds,
         ));
+        let catalog = Arc::new(catalog::ModelCatalog::from_config(&config.models));
 
         Self {
             registry,
             cache,
+            catalog,
             config,
+            tokenizers: Arc::new(DashMap::new()),
+            chat_templates: Arc::new(DashMap::new()),
         }
     }
 
@@ -36,13 +60,19 @@ impl ModelManager {
         let default_model = &self.config.models.default;
         tracing::info!("Loading default model: {}", default_model);
         
-        loader::load_model(
-            default_model,
-            &self.config.models.cache_dir,
-            self.config.models.auto_download,
-        ).await?;
-        
-        self.registry.register_model(default_model.clone()).await;
+        let entry = self.catalog.resolve(default_model)?;
+        let span = tracing::info_span!("model_load", model = %default_model);
+        async {
+            let model_path = loader::load_model(
+                &entry,
+                &self.config.models.cache_dir,
+                self.config.models.auto_download,
+            ).await?;
+            
+            self.load_tokenizer(default_model, &model_path)?;
+            self.registry.register_model(default_model.clone()).await;
+            anyhow::Ok(())
+        }.instrument(span).await?;
         tracing::info!("Default model loaded successfully");
         
         Ok(())
@@ -51,15 +81,7 @@ impl ModelManager {
     pub async fn switch_model(&self, model_name: &str) -> Result<()> {
         tracing::info!("Switching to model: {}", model_name);
         
-        if !self.registry.is_registered(model_name).await {
-            loader::load_model(
-                model_name,
-                &self.config.models.cache_dir,
-                self.config.models.auto_download,
-            ).await?;
-            
-            self.registry.register_model(model_name.to_string()).await;
-        }
+        self.ensure_loaded(model_name).await?;
         
         self.registry.set_active(model_name).await?;
         tracing::info!("Model switched successfully to: {}", model_name);
@@ -67,10 +89,103 @@ impl ModelManager {
         Ok(())
     }
 
+    pub async fn ensure_loaded(&self, model_name: &str) -> Result<()> {
+        if !self.registry.is_registered(model_name).await {
+            let entry = self.catalog.resolve(model_name)?;
+            let loading = |e: anyhow::Error| ModelError::Loading {
+                model: model_name.to_string(),
+                reason: format!("{:#}", e),
+            };
+            let span = tracing::info_span!("model_load", model = %model_name);
+            async {
+                let model_path = loader::load_model(
+                    &entry,
+                    &self.config.models.cache_dir,
+                    self.config.models.auto_download,
+                ).await.map_err(loading)?;
+                
+                self.load_tokenizer(model_name, &model_path).map_err(loading)?;
+                self.registry.register_model(model_name.to_string()).await;
+                Ok::<_, ModelError>(())
+            }.instrument(span).await?;
+        }
+        
+        Ok(())
+    }
+
+    fn load_tokenizer(&self, model_name: &str, model_path: &PathBuf) -> Result<()> {
+        let tokenizer = CustomTokenizer::load_from_dir(model_path)?;
+        self.tokenizers.insert(model_name.to_string(), Arc::new(tokenizer));
+        self.chat_templates.remove(model_name);
+        Ok(())
+    }
+
+    /// Directory the model's files are downloaded to.
+    pub fn model_path(&self, model_name: &str) -> Result<PathBuf> {
+        Ok(self.catalog.resolve(model_name)?.cache_path(&self.config.models.cache_dir))
+    }
+
+    /// The tokenizer of `model_name` if it is already loaded.
+    pub fn loaded_tokenizer(&self, model_name: &str) -> Option<Arc<CustomTokenizer>> {
+        self.tokenizers.get(model_name).map(|tokenizer| tokenizer.clone())
+    }
+
+    pub fn get_tokenizer(&self, model_name: &str) -> Arc<CustomTokenizer> {
+        self.tokenizers
+            .get(model_name)
+            .map(|tokenizer| tokenizer.clone())
+            .unwrap_or_else(|| Arc::new(CustomTokenizer::new()))
+    }
+
+    /// The chat template for `model_name`: the config.yaml override if set,
+    /// otherwise the one from the model's tokenizer_config.json. Compiled
+    /// once per loaded model.
+    pub fn get_chat_template(&self, model_name: &str) -> Result<Option<Arc<ChatTemplate>>> {
+        if let Some(template) = self.chat_templates.get(model_name) {
+            return Ok(template.clone());
+        }
+        
+        let template = self.compile_chat_template(model_name)?.map(Arc::new);
+        // Until the tokenizer is loaded its template isn't known yet
+        if self.tokenizers.contains_key(model_name) {
+            self.chat_templates.insert(model_name.to_string(), template.clone());
+        }
+        Ok(template)
+    }
+
+    fn compile_chat_template(&self, model_name: &str) -> Result<Option<ChatTemplate>> {
+        let tokenizer = self.get_tokenizer(model_name);
+        let settings = tokenizer.settings();
+        
+        let configured = self.config.model_info(model_name)
+            .and_then(|info| info.chat_template.clone())
+            .map(|template| (template, ChatTemplateSource::Config));
+        let shipped = settings.chat_template.clone()
+            .map(|template| (template, ChatTemplateSource::TokenizerConfig));
+        
+        configured
+            .or(shipped)
+            .map(|(template, origin)| {
+                ChatTemplate::new(template, origin, settings.bos_token.clone(), settings.eos_token.clone())
+            })
+            .transpose()
+    }
+
     pub async fn get_active_model(&self) -> Option<String> {
         self.registry.get_active().await
     }
 
+    /// `requested` loaded on demand, or the active model.
+    pub async fn resolve_model(&self, requested: Option<&str>) -> Result<String> {
+        match requested {
+            Some(model_name) => {
+                self.ensure_loaded(model_name).await?;
+                Ok(model_name.to_string())
+            }
+            None => Ok(self.get_active_model().await.ok_or(ModelError::NoActiveModel)?),
+        }
+    }
+
     pub async fn list_models(&self) -> Vec<String> {
         self.registry.list_all().await
     }
@@ -1,16 +1,72 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
-use prometheus::{IntCounter, Histogram, Registry, Encoder, TextEncoder};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+use crate::inference::timings::{Stage, StageTimings};
+use crate::model::catalog::CatalogEntry;
 
 pub mod logger;
+pub mod logfmt;
+pub mod rotation;
 pub mod benchmark;
+pub mod exporter;
+
+/// Histogram buckets for millisecond timings. The prometheus defaults are
+/// sized for seconds and would put every latency in `+Inf`.
+pub const LATENCY_BUCKETS_MS: &[f64] = &[
+    1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0, 30000.0,
+];
+
+/// Histogram buckets for pipeline stages, which often take well under a
+/// millisecond.
+pub const STAGE_BUCKETS_MS: &[f64] = &[
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 10000.0,
+];
+
+/// Histogram buckets for batch sizes.
+pub const BATCH_SIZE_BUCKETS: &[f64] = &[1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0];
+
+/// Model label for requests that failed before a model was chosen.
+pub const NO_MODEL: &str = "none";
+
+/// Model label for names outside the catalog, so unlisted models can't grow
+/// the number of series.
+pub const OTHER_MODEL: &str = "other";
 
 #[derive(Clone)]
 pub struct MetricsCollector {
-    pub total_requests: Arc<IntCounter>,
-    pub inference_latency: Arc<Histogram>,
-    pub batch_size: Arc<Histogram>,
+    /// `endpoint`, `model`, `version`, `task`, `status`
+    pub requests: Arc<IntCounterVec>,
+    /// `endpoint`, `model`, `version`, `task`
+    pub request_latency: Arc<HistogramVec>,
+    /// `endpoint`, `model`, `version`, `task`, `code`
+    pub errors: Arc<IntCounterVec>,
+    /// `model`, `version`, `task`
+    pub inference_latency: Arc<HistogramVec>,
+    /// `stage`, `model`, `version`, `task`
+    pub stage_latency: Arc<HistogramVec>,
+    /// `model`, `version`, `task`
+    pub batch_size: Arc<HistogramVec>,
+    /// `model`, `version`, `task`
+    pub input_tokens: Arc<IntCounterVec>,
+    /// `model`, `version`, `task`
+    pub output_tokens: Arc<IntCounterVec>,
+    pub queue_wait: Arc<Histogram>,
+    pub shed_requests: Arc<IntCounter>,
+    pub loaded_models: Arc<IntGauge>,
+    pub queue_depth: Arc<IntGauge>,
+    pub in_flight: Arc<IntGauge>,
+    /// `model`, `version`; size of the loaded weights
+    pub model_memory: Arc<IntGaugeVec>,
+    /// Catalog name -> (revision, task), the only model label values
+    known_models: Arc<HashMap<String, (String, String)>>,
     stats: Arc<RwLock<MetricsStats>>,
     registry: Arc<Registry>,
 }
@@ -24,33 +80,148 @@ struct MetricsStats {
     min_latency_ms: u64,
 }
 
+const MODEL_LABELS: &[&str] = &["model", "version", "task"];
+const REQUEST_LABELS: &[&str] = &["endpoint", "model", "version", "task", "status"];
+const ENDPOINT_LABELS: &[&str] = &["endpoint", "model", "version", "task"];
+const ERROR_LABELS: &[&str] = &["endpoint", "model", "version", "task", "code"];
+const STAGE_LABELS: &[&str] = &["stage", "model", "version", "task"];
+
 impl MetricsCollector {
-    pub fn new() -> Self {
+    /// `models` are the catalog entries allowed as `model` label values.
+    pub fn new(models: impl IntoIterator<Item = CatalogEntry>) -> Self {
         let registry = Registry::new();
         
-        let total_requests = IntCounter::new(
-            "transformer_forge_total_requests",
-            "Total number of inference requests"
+        let requests = IntCounterVec::new(
+            Opts::new("transformer_forge_requests_total", "HTTP requests by endpoint, model and status"),
+            REQUEST_LABELS
+        ).unwrap();
+        
+        let request_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "transformer_forge_request_latency_ms",
+                "HTTP request latency in milliseconds"
+            )
+            .buckets(LATENCY_BUCKETS_MS.to_vec()),
+            ENDPOINT_LABELS
+        ).unwrap();
+        
+        let errors = IntCounterVec::new(
+            Opts::new("transformer_forge_errors_total", "Error responses by error code"),
+            ERROR_LABELS
+        ).unwrap();
+        
+        let inference_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "transformer_forge_inference_latency_ms",
+                "Inference latency in milliseconds"
+            )
+            .buckets(LATENCY_BUCKETS_MS.to_vec()),
+            MODEL_LABELS
+        ).unwrap();
+        
+        let stage_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "transformer_forge_stage_latency_ms",
+                "Time spent in each inference stage in milliseconds"
+            )
+            .buckets(STAGE_BUCKETS_MS.to_vec()),
+            STAGE_LABELS
+        ).unwrap();
+        
+        let batch_size = HistogramVec::new(
+            HistogramOpts::new(
+                "transformer_forge_batch_size",
+                "Batch processing size"
+            )
+            .buckets(BATCH_SIZE_BUCKETS.to_vec()),
+            MODEL_LABELS
+        ).unwrap();
+        
+        let input_tokens = IntCounterVec::new(
+            Opts::new("transformer_forge_input_tokens_total", "Tokens fed to the model"),
+            MODEL_LABELS
+        ).unwrap();
+        
+        let output_tokens = IntCounterVec::new(
+            Opts::new(
+                "transformer_forge_output_tokens_total",
+                "Tokens of text returned from the model: entity words and answers"
+            ),
+            MODEL_LABELS
+        ).unwrap();
+        
+        let queue_wait = Histogram::with_opts(
+            HistogramOpts::new(
+                "transformer_forge_queue_wait_ms",
+                "Time spent in the admission queue in milliseconds"
+            )
+            .buckets(LATENCY_BUCKETS_MS.to_vec())
+        ).unwrap();
+        
+        let shed_requests = IntCounter::new(
+            "transformer_forge_shed_requests_total",
+            "Requests rejected or dropped by the admission queue"
+        ).unwrap();
+        
+        let loaded_models = IntGauge::new(
+            "transformer_forge_loaded_models",
+            "Model pipelines resident in memory"
+        ).unwrap();
+        
+        let queue_depth = IntGauge::new(
+            "transformer_forge_queue_depth",
+            "Requests waiting in the admission queue"
         ).unwrap();
         
-        let inference_latency = Histogram::new(
-            "transformer_forge_inference_latency_ms",
-            "Inference latency in milliseconds"
+        let in_flight = IntGauge::new(
+            "transformer_forge_in_flight_requests",
+            "HTTP requests being handled"
         ).unwrap();
         
-        let batch_size = Histogram::new(
-            "transformer_forge_batch_size",
-            "Batch processing size"
+        let model_memory = IntGaugeVec::new(
+            Opts::new("transformer_forge_model_memory_bytes", "Size of each loaded model's weights"),
+            &["model", "version"]
         ).unwrap();
         
-        registry.register(Box::new(total_requests.clone())).unwrap();
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(request_latency.clone())).unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
         registry.register(Box::new(inference_latency.clone())).unwrap();
+        registry.register(Box::new(stage_latency.clone())).unwrap();
         registry.register(Box::new(batch_size.clone())).unwrap();
+        registry.register(Box::new(input_tokens.clone())).unwrap();
+        registry.register(Box::new(output_tokens.clone())).unwrap();
+        registry.register(Box::new(queue_wait.clone())).unwrap();
+        registry.register(Box::new(shed_requests.clone())).unwrap();
+        registry.register(Box::new(loaded_models.clone())).unwrap();
+        registry.register(Box::new(queue_depth.clone())).unwrap();
+        registry.register(Box::new(in_flight.clone())).unwrap();
+        registry.register(Box::new(model_memory.clone())).unwrap();
+        
+        let known_models = models
+            .into_iter()
+            .map(|entry| {
+                let task = entry.task.unwrap_or_else(|| "unknown".to_string());
+                (entry.name, (entry.revision, task))
+            })
+            .collect();
         
         Self {
-            total_requests: Arc::new(total_requests),
+            requests: Arc::new(requests),
+            request_latency: Arc::new(request_latency),
+            errors: Arc::new(errors),
             inference_latency: Arc::new(inference_latency),
+            stage_latency: Arc::new(stage_latency),
             batch_size: Arc::new(batch_size),
+            input_tokens: Arc::new(input_tokens),
+            output_tokens: Arc::new(output_tokens),
+            queue_wait: Arc::new(queue_wait),
+            shed_requests: Arc::new(shed_requests),
+            loaded_models: Arc::new(loaded_models),
+            queue_depth: Arc::new(queue_depth),
+            in_flight: Arc::new(in_flight),
+            model_memory: Arc::new(model_memory),
+            known_models: Arc::new(known_models),
             stats: Arc::new(RwLock::new(MetricsStats {
                 total_inferences: 0,
                 total_batch_inferences: 0,
@@ -62,9 +233,43 @@ impl MetricsCollector {
         }
     }
 
-    pub async fn record_inference(&self, latency_ms: u64) {
-        self.total_requests.inc();
-        self.inference_latency.observe(latency_ms as f64);
+    /// `[model, version, task]` label values. Names outside the catalog
+    /// collapse into `other`.
+    pub fn model_labels<'a>(&'a self, model: Option<&'a str>) -> [&'a str; 3] {
+        match model {
+            None => [NO_MODEL, "", ""],
+            Some(model) => match self.known_models.get_key_value(model) {
+                Some((name, (version, task))) => [name, version, task],
+                None => [OTHER_MODEL, "", ""],
+            },
+        }
+    }
+
+    /// One finished HTTP request. `endpoint` is the matched route template,
+    /// never the raw path.
+    pub fn record_request(&self, endpoint: &str, model: Option<&str>, status: u16, error_code: Option<&str>, latency_ms: u64) {
+        let [model, version, task] = self.model_labels(model);
+        
+        self.requests
+            .with_label_values(&[endpoint, model, version, task, &status.to_string()])
+            .inc();
+        self.request_latency
+            .with_label_values(&[endpoint, model, version, task])
+            .observe(latency_ms as f64);
+        if let Some(code) = error_code {
+            self.errors
+                .with_label_values(&[endpoint, model, version, task, code])
+                .inc();
+        }
+    }
+
+    /// One finished inference. Classifiers return labels, not text, so
+    /// they report no output tokens.
+    pub async fn record_inference(&self, model: &str, latency_ms: u64, input_tokens: usize, output_tokens: usize) {
+        let labels = self.model_labels(Some(model));
+        self.inference_latency.with_label_values(&labels).observe(latency_ms as f64);
+        self.input_tokens.with_label_values(&labels).inc_by(input_tokens as u64);
+        self.output_tokens.with_label_values(&labels).inc_by(output_tokens as u64);
         
         let mut stats = self.stats.write().await;
         stats.total_inferences += 1;
@@ -76,14 +281,34 @@ impl MetricsCollector {
         stats.avg_latency_ms = (stats.avg_latency_ms * (total - 1.0) + latency_ms as f64) / total;
     }
 
-    pub async fn record_batch_inference(&self, batch_size: u64, latency_ms: u64) {
-        self.batch_size.observe(batch_size as f64);
-        self.record_inference(latency_ms).await;
+    pub async fn record_batch_inference(&self, model: &str, batch_size: u64, latency_ms: u64, input_tokens: usize) {
+        self.batch_size.with_label_values(&self.model_labels(Some(model))).observe(batch_size as f64);
+        self.record_inference(model, latency_ms, input_tokens, 0).await;
         
         let mut stats = self.stats.write().await;
         stats.total_batch_inferences += 1;
     }
 
+    /// Stage breakdown of one inference. Queue wait has its own histogram.
+    pub fn record_stage_timings(&self, model: &str, timings: &StageTimings) {
+        let [model, version, task] = self.model_labels(Some(model));
+        for stage in Stage::ALL {
+            if stage == Stage::QueueWait {
+                continue;
+            }
+            self.stage_latency
+                .with_label_values(&[stage.name(), model, version, task])
+                .observe(timings.get(stage));
+        }
+    }
+
+    /// A pipeline finished loading; `loaded` is how many are now resident.
+    pub fn record_model_loaded(&self, model: &str, weight_bytes: u64, loaded: usize) {
+        let [model, version, _] = self.model_labels(Some(model));
+        self.model_memory.with_label_values(&[model, version]).set(weight_bytes as i64);
+        self.loaded_models.set(loaded as i64);
+    }
+
     pub async fn get_summary(&self) -> MetricsSummary {
         let stats = self.stats.read().await;
         
@@ -115,3 +340,73 @@ pub struct MetricsSummary {
     pub min_latency_ms: u64,
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
+
+tokio::task_local! {
+    static REQUEST_MODEL: RefCell<Option<String>>;
+}
+
+/// Notes which model is serving the current request, for the `model` label
+/// of the request metrics. A no-op outside `with_request_model`.
+pub fn note_request_model(model: &str) {
+    let _ = REQUEST_MODEL.try_with(|current| *current.borrow_mut() = Some(model.to_string()));
+}
+
+/// Runs a request and returns the model it noted, if any.
+pub async fn with_request_model<F: Future>(request: F) -> (F::Output, Option<String>) {
+    REQUEST_MODEL
+        .scope(RefCell::new(None), async {
+            let output = request.await;
+            let model = REQUEST_MODEL.with(|current| current.borrow_mut().take());
+            (output, model)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, task: &str) -> CatalogEntry {
+        CatalogEntry {
+            name: name.to_string(),
+            repo: format!("org/{}", name),
+            revision: "main".to_string(),
+            task: Some(task.to_string()),
+            listed: true,
+        }
+    }
+
+    #[test]
+    fn test_latencies_land_in_millisecond_buckets() {
+        let metrics = MetricsCollector::new(Vec::new());
+        metrics.queue_wait.observe(42.0);
+
+        let text = metrics.export_prometheus();
+        assert!(text.contains("transformer_forge_queue_wait_ms_bucket{le=\"50\"} 1"));
+        assert!(text.contains("transformer_forge_queue_wait_ms_bucket{le=\"25\"} 0"));
+    }
+
+    #[test]
+    fn test_model_labels_are_bounded_to_the_catalog() {
+        let metrics = MetricsCollector::new(vec![entry("bert-ner", "token-classification")]);
+
+        assert_eq!(metrics.model_labels(Some("bert-ner")), ["bert-ner", "main", "token-classification"]);
+        assert_eq!(metrics.model_labels(Some("someone/else")), [OTHER_MODEL, "", ""]);
+        assert_eq!(metrics.model_labels(None), [NO_MODEL, "", ""]);
+
+        metrics.record_request("/ner", Some("someone/else"), 404, Some("model_not_found"), 3);
+        let text = metrics.export_prometheus();
+        assert!(text.contains("model=\"other\""));
+        assert!(!text.contains("someone/else"));
+    }
+
+    #[tokio::test]
+    async fn test_request_model_is_scoped_to_the_request() {
+        let (_, model) = with_request_model(async { note_request_model("bert-ner") }).await;
+        assert_eq!(model.as_deref(), Some("bert-ner"));
+
+        note_request_model("outside");
+        let (_, model) = with_request_model(async {}).await;
+        assert_eq!(model, None);
+    }
+}
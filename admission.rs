@@ -0,0 +1,290 @@
+// Admission control in front of the inference engine: at most
+// `performance.async_workers` requests run at once, up to
+// `performance.queue_size` more wait, highest priority first.
+
+use anyhow::Result;
+use prometheus::IntGauge;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+use super::{Deadline, InferenceError};
+
+/// How soon a rejected or shed client should retry.
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Throughput work; shed first under overload
+    Batch,
+    /// A user is waiting on the response
+    Interactive,
+}
+
+impl Priority {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "batch" => Some(Self::Batch),
+            "interactive" => Some(Self::Interactive),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum AdmissionError {
+    #[error("Server is at capacity, try again later")]
+    QueueFull,
+    #[error("Request was dropped from the queue to make room for higher-priority work")]
+    Shed,
+}
+
+/// A running slot. Dropping it hands the slot to the next waiter.
+pub struct Permit {
+    inner: Arc<Inner>,
+    /// Time spent queued before this slot was granted
+    pub waited: Duration,
+    /// Cleared for a permit that never reached its waiter, whose slot is
+    /// still being handed out by `release`
+    issued: bool,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.issued {
+            self.inner.release();
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AdmissionController {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    workers: usize,
+    capacity: usize,
+    state: Mutex<QueueState>,
+    /// Kept at the number of live waiters on every queue change
+    depth: IntGauge,
+}
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    next_seq: u64,
+    /// Highest priority first, then oldest first
+    waiting: BTreeMap<(Reverse<Priority>, u64), Waiter>,
+}
+
+struct Waiter {
+    enqueued_at: Instant,
+    sender: oneshot::Sender<Result<Permit, AdmissionError>>,
+}
+
+impl QueueState {
+    /// Waiters whose request is still around.
+    fn live_waiters(&self) -> usize {
+        self.waiting.values().filter(|waiter| !waiter.sender.is_closed()).count()
+    }
+}
+
+impl AdmissionController {
+    /// `depth` reports the queue length, e.g. to the `queue_depth` gauge.
+    pub fn new(workers: usize, capacity: usize, depth: IntGauge) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                workers: workers.max(1),
+                capacity,
+                state: Mutex::new(QueueState::default()),
+                depth,
+            }),
+        }
+    }
+
+    /// Waits for a running slot until `deadline`. When the queue is full a
+    /// request either displaces the newest lower-priority waiter or is
+    /// rejected.
+    pub async fn acquire(&self, priority: Priority, deadline: Option<Deadline>) -> Result<Permit> {
+        let (receiver, key) = {
+            let mut state = self.inner.state.lock().unwrap();
+            // Waiters whose request went away no longer hold a place
+            state.waiting.retain(|_, waiter| !waiter.sender.is_closed());
+
+            let outranked = state.waiting.keys().next().is_some_and(|(Reverse(p), _)| *p >= priority);
+            if state.running < self.inner.workers && !outranked {
+                state.running += 1;
+                self.inner.report_depth(&state);
+                return Ok(self.inner.permit(Duration::ZERO));
+            }
+
+            if state.waiting.len() >= self.inner.capacity {
+                let lowest = state.waiting.keys().next_back().copied()
+                    .filter(|(Reverse(p), _)| *p < priority);
+                let Some(key) = lowest else {
+                    self.inner.report_depth(&state);
+                    return Err(AdmissionError::QueueFull.into());
+                };
+                if let Some(shed) = state.waiting.remove(&key) {
+                    let _ = shed.sender.send(Err(AdmissionError::Shed));
+                }
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.insert((Reverse(priority), seq), Waiter { enqueued_at: Instant::now(), sender });
+            self.inner.report_depth(&state);
+            (receiver, (Reverse(priority), seq))
+        };
+
+        let Some(deadline) = deadline else {
+            return receiver.await.map_err(|_| AdmissionError::Shed)?.map_err(Into::into);
+        };
+
+        match tokio::time::timeout(deadline.remaining(), receiver).await {
+            Ok(granted) => granted.map_err(|_| AdmissionError::Shed)?.map_err(Into::into),
+            // Dropping the receiver frees the place; a permit granted in the
+            // meantime is dropped with it and passed on
+            Err(_) => {
+                let mut state = self.inner.state.lock().unwrap();
+                state.waiting.remove(&key);
+                self.inner.report_depth(&state);
+                Err(InferenceError::Timeout { timeout_ms: deadline.timeout_ms() }.into())
+            }
+        }
+    }
+
+    /// `(running, waiting)`
+    pub fn load(&self) -> (usize, usize) {
+        let state = self.inner.state.lock().unwrap();
+        (state.running, state.live_waiters())
+    }
+}
+
+impl Inner {
+    fn permit(self: &Arc<Self>, waited: Duration) -> Permit {
+        Permit { inner: self.clone(), waited, issued: true }
+    }
+
+    fn report_depth(&self, state: &QueueState) {
+        self.depth.set(state.live_waiters() as i64);
+    }
+
+    /// Hands the slot to the best live waiter, or frees it.
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        while let Some((_, waiter)) = state.waiting.pop_first() {
+            let permit = self.permit(waiter.enqueued_at.elapsed());
+            match waiter.sender.send(Ok(permit)) {
+                Ok(()) => {
+                    self.report_depth(&state);
+                    return;
+                }
+                Err(unsent) => {
+                    // The waiter is gone; its permit was never issued
+                    if let Ok(mut permit) = unsent {
+                        permit.issued = false;
+                    }
+                }
+            }
+        }
+        state.running -= 1;
+        self.report_depth(&state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller(workers: usize, capacity: usize) -> AdmissionController {
+        AdmissionController::new(workers, capacity, IntGauge::new("queue_depth", "Queued requests").unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_waiters_are_admitted_by_priority() {
+        let controller = controller(1, 10);
+        let running = controller.acquire(Priority::Interactive, None).await.unwrap();
+
+        let batch = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire(Priority::Batch, None).await.map(|_| Instant::now()) }
+        });
+        tokio::task::yield_now().await;
+        let interactive = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire(Priority::Interactive, None).await.map(|_| Instant::now()) }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(controller.load(), (1, 2));
+        assert_eq!(controller.inner.depth.get(), 2);
+
+        drop(running);
+        let (batch, interactive) = (batch.await.unwrap().unwrap(), interactive.await.unwrap().unwrap());
+        assert!(interactive <= batch);
+        assert_eq!(controller.load(), (0, 0));
+        assert_eq!(controller.inner.depth.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_permits_for_departed_waiters_are_not_leaked() {
+        let controller = controller(1, 10);
+        let running = controller.acquire(Priority::Batch, None).await.unwrap();
+
+        let departed = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire(Priority::Batch, None).await.map(|_| ()) }
+        });
+        tokio::task::yield_now().await;
+        departed.abort();
+        let _ = departed.await;
+
+        drop(running);
+        assert_eq!(controller.load(), (0, 0));
+        assert_eq!(Arc::strong_count(&controller.inner), 1);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_sheds_lower_priority() {
+        let controller = controller(1, 1);
+        let _running = controller.acquire(Priority::Batch, None).await.unwrap();
+
+        let queued = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire(Priority::Batch, None).await }
+        });
+        tokio::task::yield_now().await;
+
+        // Same priority cannot displace it
+        let rejected = controller.acquire(Priority::Batch, None).await.err().unwrap();
+        assert!(matches!(rejected.downcast_ref(), Some(AdmissionError::QueueFull)));
+
+        let _interactive = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire(Priority::Interactive, None).await }
+        });
+        let shed = queued.await.unwrap().err().unwrap();
+        assert!(matches!(shed.downcast_ref(), Some(AdmissionError::Shed)));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_expires_while_queued() {
+        let controller = controller(1, 1);
+        let _running = controller.acquire(Priority::Interactive, None).await.unwrap();
+
+        let deadline = Deadline::after(Duration::from_millis(10));
+        let error = controller.acquire(Priority::Interactive, Some(deadline)).await.err().unwrap();
+        assert!(matches!(error.downcast_ref(), Some(InferenceError::Timeout { .. })));
+        assert_eq!(controller.inner.depth.get(), 0);
+
+        // The expired waiter no longer takes up the queue
+        let _ = tokio::time::timeout(Duration::from_millis(10), controller.acquire(Priority::Batch, None)).await;
+        assert_eq!(controller.load(), (1, 0));
+    }
+}
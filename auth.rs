@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::Instrument;
+
+use super::error::ApiError;
+use crate::config::{ApiKeyConfig, AuthConfig};
+
+/// Extra keys, as a JSON array of `auth.keys` entries, so secrets can stay
+/// out of config.yaml.
+pub const API_KEYS_ENV: &str = "API_KEYS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    /// Inference, tokenization and chat templates
+    #[serde(rename = "predict")]
+    Predict,
+    /// Embedding endpoints
+    #[serde(rename = "embed")]
+    Embed,
+    /// Switching the active model
+    #[serde(rename = "admin:models")]
+    AdminModels,
+    /// Cache inspection, flushing and warmup
+    #[serde(rename = "admin:cache")]
+    AdminCache,
+    /// Reading and changing the log level
+    #[serde(rename = "admin:logs")]
+    AdminLogs,
+    /// Metrics and model stats
+    #[serde(rename = "metrics")]
+    Metrics,
+}
+
+/// A key that passed authentication. Never holds the secret.
+pub type Caller = Arc<ApiKeyConfig>;
+
+/// Id of the authenticated key, left on the response for the access log.
+#[derive(Debug, Clone)]
+pub struct KeyId(pub String);
+
+/// Configured keys, indexed by the SHA-256 of the secret.
+pub struct KeyStore {
+    enabled: bool,
+    by_hash: HashMap<String, Caller>,
+}
+
+impl KeyStore {
+    pub fn from_config(config: &AuthConfig) -> Result<Self> {
+        let mut keys = config.keys.clone();
+        if let Ok(json) = std::env::var(API_KEYS_ENV) {
+            let extra: Vec<ApiKeyConfig> = serde_json::from_str(&json)
+                .with_context(|| format!("{} is not a JSON array of API keys", API_KEYS_ENV))?;
+            keys.extend(extra);
+        }
+
+        let mut by_hash = HashMap::new();
+        for key in keys {
+            let hash = key.key_hash.trim().to_lowercase();
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                anyhow::bail!("API key '{}' needs key_hash as a hex SHA-256 digest", key.id);
+            }
+            if by_hash.insert(hash, Arc::new(key.clone())).is_some() {
+                anyhow::bail!("API key '{}' duplicates another key", key.id);
+            }
+        }
+
+        if config.enabled && by_hash.is_empty() {
+            tracing::warn!("Authentication is enabled but no API keys are configured");
+        }
+
+        Ok(Self {
+            enabled: config.enabled,
+            by_hash,
+        })
+    }
+
+    /// The key presented as `Authorization: Bearer <key>` or `x-api-key`.
+    /// `Ok(None)` only when authentication is disabled.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Option<Caller>, ApiError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        let secret = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| headers.get("x-api-key").and_then(|value| value.to_str().ok()))
+            .map(str::trim)
+            .ok_or_else(|| ApiError::Unauthorized("Missing API key".to_string()))?;
+
+        self.by_hash
+            .get(&hash_key(secret))
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| ApiError::Unauthorized("Invalid API key".to_string()))
+    }
+}
+
+/// What goes in `key_hash` for a secret.
+pub fn hash_key(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+tokio::task_local! {
+    static CALLER: Option<Caller>;
+}
+
+/// Key of the request being handled. `None` when authentication is off or
+/// outside `authorize`.
+pub fn current_caller() -> Option<Caller> {
+    CALLER.try_with(|caller| caller.clone()).ok().flatten()
+}
+
+/// Rejects callers the key's `models` list doesn't cover.
+pub fn check_model_access(model: &str) -> Result<(), ApiError> {
+    match current_caller() {
+        Some(caller) if !caller.allows_model(model) => Err(ApiError::Forbidden(format!(
+            "API key '{}' may not use model '{}'",
+            caller.id, model
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// State for `authorize`: the key store and the scope a route group needs.
+#[derive(Clone)]
+pub struct Guard {
+    pub keys: Arc<KeyStore>,
+    pub scope: Option<Scope>,
+}
+
+impl Guard {
+    pub fn new(keys: &Arc<KeyStore>, scope: Option<Scope>) -> Self {
+        Self { keys: keys.clone(), scope }
+    }
+}
+
+/// Authenticates the request and checks the guard's scope. The key id is
+/// attached to a span around the rest of the request so every log line
+/// carries it.
+pub async fn authorize(
+    State(guard): State<Guard>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let caller = match guard.keys.authenticate(req.headers()) {
+        Ok(caller) => caller,
+        Err(e) => return e.into_response(),
+    };
+
+    let Some(key) = caller.clone() else {
+        return CALLER.scope(None, next.run(req)).await;
+    };
+
+    if let Some(scope) = guard.scope {
+        if !key.scopes.contains(&scope) {
+            return ApiError::Forbidden(format!("API key '{}' lacks the {:?} scope", key.id, scope)).into_response();
+        }
+    }
+
+    let span = tracing::info_span!("api_key", key_id = %key.id);
+    let mut response = CALLER.scope(caller, next.run(req)).instrument(span).await;
+    response.extensions_mut().insert(KeyId(key.id.clone()));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn store() -> KeyStore {
+        let config = AuthConfig {
+            enabled: true,
+            keys: vec![ApiKeyConfig {
+                id: "ci".to_string(),
+                key_hash: hash_key("s3cret"),
+                scopes: vec![Scope::Predict],
+                models: vec!["bert-base-uncased".to_string()],
+                priority: None,
+                rate_limits: None,
+                quotas: None,
+            }],
+        };
+        KeyStore::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn test_keys_match_by_hash() {
+        let keys = store();
+        let mut headers = HeaderMap::new();
+        assert_eq!(keys.authenticate(&headers).err().unwrap().code(), "unauthorized");
+
+        headers.insert("x-api-key", HeaderValue::from_static("wrong"));
+        assert!(keys.authenticate(&headers).is_err());
+
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer s3cret"));
+        let caller = keys.authenticate(&headers).unwrap().unwrap();
+        assert_eq!(caller.id, "ci");
+        assert!(caller.allows_model("bert-base-uncased"));
+        assert!(!caller.allows_model("roberta-large"));
+    }
+
+    #[test]
+    fn test_scopes_use_colon_names() {
+        let scopes: Vec<Scope> = serde_json::from_str(r#"["predict", "admin:models", "admin:cache"]"#).unwrap();
+        assert_eq!(scopes, vec![Scope::Predict, Scope::AdminModels, Scope::AdminCache]);
+    }
+}
@@ -1,54 +1,113 @@
 use dashmap::DashMap;
+use serde::Serialize;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 
+use crate::inference::InferenceOutput;
+
 pub struct ModelCache {
     cache: Arc<DashMap<String, CachedItem>>,
+    counters: Arc<DashMap<String, HitCounter>>,
     max_entries: usize,
     ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
 }
 
 struct CachedItem {
-    data: Vec<f32>,
+    model: String,
+    data: InferenceOutput,
+    size_bytes: usize,
     timestamp: Instant,
 }
 
+#[derive(Default)]
+struct HitCounter {
+    hits: u64,
+    misses: u64,
+}
+
 impl ModelCache {
     pub fn new(max_entries: usize, ttl_seconds: u64) -> Self {
         Self {
             cache: Arc::new(DashMap::new()),
+            counters: Arc::new(DashMap::new()),
             max_entries,
             ttl: Duration::from_secs(ttl_seconds),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
         }
     }
 
-    pub fn get(&self, key: &str) -> Option<Vec<f32>> {
-        if let Some(item) = self.cache.get(key) {
+    fn key(model: &str, input: &str) -> String {
+        format!("{}\u{1f}{}", model, input)
+    }
+
+    pub fn get(&self, model: &str, input: &str) -> Option<InferenceOutput> {
+        let span = tracing::info_span!("cache_lookup", model = model, hit = tracing::field::Empty);
+        let _entered = span.enter();
+        let key = Self::key(model, input);
+        let mut found = None;
+
+        if let Some(item) = self.cache.get(&key) {
             if item.timestamp.elapsed() < self.ttl {
-                return Some(item.data.clone());
-            } else {
-                // Remove expired item
-                self.cache.remove(key);
+                found = Some(item.data.clone());
             }
         }
-        None
+
+        if found.is_none() {
+            // Remove expired item (no-op if it was never cached)
+            self.cache.remove(&key);
+        }
+
+        self.record_lookup(model, found.is_some());
+        span.record("hit", found.is_some());
+        found
+    }
+
+    pub fn contains(&self, model: &str, input: &str) -> bool {
+        self.cache
+            .get(&Self::key(model, input))
+            .map(|item| item.timestamp.elapsed() < self.ttl)
+            .unwrap_or(false)
     }
 
-    pub fn insert(&self, key: String, data: Vec<f32>) {
+    pub fn insert(&self, model: &str, input: &str, data: InferenceOutput) {
+        let key = Self::key(model, input);
+
         // Evict oldest if cache is full
-        if self.cache.len() >= self.max_entries {
+        if self.cache.len() >= self.max_entries && !self.cache.contains_key(&key) {
             self.evict_oldest();
         }
 
+        let size_bytes = key.len()
+            + data.label.len()
+            + data.embeddings.len() * std::mem::size_of::<f32>()
+            + std::mem::size_of::<CachedItem>();
+
         self.cache.insert(
             key,
             CachedItem {
+                model: model.to_string(),
                 data,
+                size_bytes,
                 timestamp: Instant::now(),
             },
         );
     }
 
+    fn record_lookup(&self, model: &str, hit: bool) {
+        let mut counter = self.counters.entry(model.to_string()).or_default();
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            counter.hits += 1;
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            counter.misses += 1;
+        }
+    }
+
     fn evict_oldest(&self) {
         let mut oldest_key: Option<String> = None;
         let mut oldest_time = Instant::now();
@@ -65,8 +124,57 @@ impl ModelCache {
         }
     }
 
-    pub fn clear(&self) {
+    /// Removes every entry and returns how many were dropped.
+    pub fn clear(&self) -> usize {
+        let removed = self.cache.len();
         self.cache.clear();
+        removed
+    }
+
+    /// Removes the entries of a single model and returns how many were dropped.
+    pub fn clear_model(&self, model: &str) -> usize {
+        let before = self.cache.len();
+        self.cache.retain(|_, item| item.model != model);
+        before.saturating_sub(self.cache.len())
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let mut models: std::collections::BTreeMap<String, ModelCacheStats> = Default::default();
+        let mut bytes = 0;
+
+        for entry in self.cache.iter() {
+            let item = entry.value();
+            bytes += item.size_bytes;
+
+            let model = models
+                .entry(item.model.clone())
+                .or_insert_with(|| ModelCacheStats::empty(&item.model));
+            model.entries += 1;
+            model.bytes += item.size_bytes;
+        }
+
+        for counter in self.counters.iter() {
+            let model = models
+                .entry(counter.key().clone())
+                .or_insert_with(|| ModelCacheStats::empty(counter.key()));
+            model.hits = counter.hits;
+            model.misses = counter.misses;
+            model.hit_ratio = hit_ratio(counter.hits, counter.misses);
+        }
+
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+
+        CacheStats {
+            entries: self.cache.len(),
+            max_entries: self.max_entries,
+            ttl_seconds: self.ttl.as_secs(),
+            bytes,
+            hits,
+            misses,
+            hit_ratio: hit_ratio(hits, misses),
+            models: models.into_values().collect(),
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -77,3 +185,116 @@ impl ModelCache {
         self.cache.is_empty()
     }
 }
+
+fn hit_ratio(hits: u64, misses: u64) -> f64 {
+    let total = hits + misses;
+    if total == 0 {
+        0.0
+    } else {
+        hits as f64 / total as f64
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub max_entries: usize,
+    pub ttl_seconds: u64,
+    pub bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_ratio: f64,
+    pub models: Vec<ModelCacheStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelCacheStats {
+    pub model: String,
+    pub entries: usize,
+    pub bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_ratio: f64,
+}
+
+impl ModelCacheStats {
+    fn empty(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            entries: 0,
+            bytes: 0,
+            hits: 0,
+            misses: 0,
+            hit_ratio: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(label: &str) -> InferenceOutput {
+        InferenceOutput {
+            label: label.to_string(),
+            score: 0.9,
+            embeddings: vec![0.0; 4],
+        }
+    }
+
+    fn model_stats<'a>(stats: &'a CacheStats, model: &str) -> &'a ModelCacheStats {
+        stats.models.iter().find(|m| m.model == model).unwrap()
+    }
+
+    #[test]
+    fn test_counters_are_kept_per_model() {
+        let cache = ModelCache::new(10, 60);
+        cache.insert("bert", "hello", output("POSITIVE"));
+
+        assert!(cache.get("bert", "hello").is_some());
+        assert!(cache.get("bert", "bye").is_none());
+        assert!(cache.get("roberta", "hello").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+
+        let bert = model_stats(&stats, "bert");
+        assert_eq!((bert.entries, bert.hits, bert.misses), (1, 1, 1));
+        assert!(bert.bytes > 0);
+
+        // Misses alone still list the model
+        let roberta = model_stats(&stats, "roberta");
+        assert_eq!((roberta.entries, roberta.hits, roberta.misses), (0, 0, 1));
+    }
+
+    #[test]
+    fn test_hit_ratio() {
+        assert_eq!(hit_ratio(0, 0), 0.0);
+        assert_eq!(hit_ratio(3, 1), 0.75);
+
+        let cache = ModelCache::new(10, 60);
+        cache.insert("bert", "hello", output("POSITIVE"));
+        for _ in 0..3 {
+            cache.get("bert", "hello");
+        }
+        cache.get("bert", "bye");
+
+        let stats = cache.stats();
+        assert_eq!(stats.hit_ratio, 0.75);
+        assert_eq!(model_stats(&stats, "bert").hit_ratio, 0.75);
+    }
+
+    #[test]
+    fn test_clear_model_keeps_other_models() {
+        let cache = ModelCache::new(10, 60);
+        cache.insert("bert", "a", output("POSITIVE"));
+        cache.insert("bert", "b", output("NEGATIVE"));
+        cache.insert("roberta", "a", output("POSITIVE"));
+
+        assert_eq!(cache.clear_model("bert"), 2);
+        assert_eq!(cache.clear_model("bert"), 0);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains("roberta", "a"));
+        assert!(!cache.contains("bert", "a"));
+    }
+}
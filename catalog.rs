@@ -0,0 +1,165 @@
+use anyhow::Result;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::ModelError;
+use crate::config::ModelsConfig;
+
+pub const DEFAULT_REVISION: &str = "main";
+
+/// A loadable model: the name clients use and the hub repo behind it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CatalogEntry {
+    pub name: String,
+    pub repo: String,
+    pub revision: String,
+    /// `None` for unlisted models, which skip task checks
+    pub task: Option<String>,
+    pub listed: bool,
+}
+
+impl CatalogEntry {
+    /// One cache directory per repo and revision, so pointing a name at a
+    /// different repo never serves the old weights.
+    pub fn cache_path(&self, cache_dir: &Path) -> PathBuf {
+        let repo = self.repo.replace('/', "--");
+        if self.revision == DEFAULT_REVISION {
+            cache_dir.join(repo)
+        } else {
+            cache_dir.join(format!("{}@{}", repo, self.revision.replace('/', "--")))
+        }
+    }
+}
+
+/// Logical model names from `models.available_models`.
+#[derive(Debug, Clone)]
+pub struct ModelCatalog {
+    entries: BTreeMap<String, CatalogEntry>,
+    allow_unlisted: bool,
+}
+
+impl ModelCatalog {
+    pub fn from_config(config: &ModelsConfig) -> Self {
+        let entries = config.available_models
+            .iter()
+            .map(|info| {
+                let entry = CatalogEntry {
+                    name: info.name.clone(),
+                    repo: info.repo.clone(),
+                    revision: info.revision.clone().unwrap_or_else(|| DEFAULT_REVISION.to_string()),
+                    task: Some(info.task.clone()),
+                    listed: true,
+                };
+                (info.name.clone(), entry)
+            })
+            .collect();
+
+        Self {
+            entries,
+            allow_unlisted: config.allow_unlisted_models,
+        }
+    }
+
+    /// Looks up `name`. Names outside the catalog are treated as hub repo
+    /// ids, but only when `allow_unlisted_models` is on.
+    pub fn resolve(&self, name: &str) -> Result<CatalogEntry> {
+        if let Some(entry) = self.entries.get(name) {
+            return Ok(entry.clone());
+        }
+
+        if !self.allow_unlisted {
+            return Err(ModelError::NotFound(name.to_string()).into());
+        }
+        if !is_valid_repo_id(name) {
+            return Err(ModelError::InvalidName(name.to_string()).into());
+        }
+
+        Ok(CatalogEntry {
+            name: name.to_string(),
+            repo: name.to_string(),
+            revision: DEFAULT_REVISION.to_string(),
+            task: None,
+            listed: false,
+        })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &CatalogEntry> {
+        self.entries.values()
+    }
+}
+
+/// `name` or `org/name` as accepted by the hub. Also keeps the id safe to
+/// use as a cache directory name.
+pub fn is_valid_repo_id(id: &str) -> bool {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(r"^[A-Za-z0-9][A-Za-z0-9._-]{0,95}(/[A-Za-z0-9][A-Za-z0-9._-]{0,95})?$").unwrap()
+    });
+
+    pattern.is_match(id) && !id.contains("..")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModelInfo;
+
+    fn config(allow_unlisted_models: bool) -> ModelsConfig {
+        ModelsConfig {
+            default: "bert-base-uncased".to_string(),
+            cache_dir: PathBuf::from("./models_cache"),
+            auto_download: true,
+            allow_unlisted_models,
+            available_models: vec![ModelInfo {
+                name: "bert-base-uncased".to_string(),
+                task: "sentiment-analysis".to_string(),
+                repo: "distilbert-base-uncased-finetuned-sst-2-english".to_string(),
+                revision: None,
+                preprocessing: None,
+                chat_template: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_resolve_maps_name_to_repo() {
+        let catalog = ModelCatalog::from_config(&config(false));
+        let entry = catalog.resolve("bert-base-uncased").unwrap();
+
+        assert_eq!(entry.repo, "distilbert-base-uncased-finetuned-sst-2-english");
+        assert_eq!(entry.revision, "main");
+        assert_eq!(entry.task.as_deref(), Some("sentiment-analysis"));
+        assert_eq!(
+            entry.cache_path(Path::new("cache")),
+            Path::new("cache").join("distilbert-base-uncased-finetuned-sst-2-english")
+        );
+    }
+
+    #[test]
+    fn test_unlisted_models_need_policy() {
+        assert!(ModelCatalog::from_config(&config(false)).resolve("gpt2").is_err());
+
+        let open = ModelCatalog::from_config(&config(true));
+        let entry = open.resolve("dslim/bert-base-NER").unwrap();
+        assert!(!entry.listed);
+        assert_eq!(entry.cache_path(Path::new("cache")), Path::new("cache").join("dslim--bert-base-NER"));
+
+        assert!(open.resolve("../etc").is_err());
+        assert!(open.resolve("a/b/c").is_err());
+    }
+
+    #[test]
+    fn test_revision_gets_its_own_cache_dir() {
+        let entry = CatalogEntry {
+            name: "ner".to_string(),
+            repo: "dslim/bert-base-NER".to_string(),
+            revision: "v1.0".to_string(),
+            task: None,
+            listed: true,
+        };
+        assert_eq!(entry.cache_path(Path::new("c")), Path::new("c").join("dslim--bert-base-NER@v1.0"));
+    }
+}
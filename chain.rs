@@ -0,0 +1,248 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use super::normalizer::{self, UnicodeForm};
+use crate::config::{AppConfig, PreprocessingConfig};
+
+/// One named step of a preprocessing chain, as written in config.yaml:
+///
+/// ```yaml
+/// - step: unicode_normalize
+///   form: nfkc
+/// - step: lowercase
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum PreprocessingStep {
+    UnicodeNormalize {
+        #[serde(default = "default_form")]
+        form: UnicodeForm,
+    },
+    StripAccents,
+    FoldFullWidth,
+    RemoveControlChars,
+    RemoveZeroWidthChars,
+    Lowercase,
+    RemoveSpecialChars,
+    /// Removes tags and decodes entities
+    StripHtml,
+    DecodeHtmlEntities,
+    StripMarkdown,
+    /// Removes URLs, or replaces them with `replacement` (e.g. `[URL]`)
+    StripUrls {
+        #[serde(default)]
+        replacement: String,
+    },
+    MaskEmails {
+        #[serde(default = "default_email_mask")]
+        mask: String,
+    },
+    CollapseWhitespace,
+    Trim,
+    Truncate {
+        max_chars: usize,
+    },
+}
+
+fn default_form() -> UnicodeForm {
+    UnicodeForm::Nfc
+}
+
+fn default_email_mask() -> String {
+    "[EMAIL]".to_string()
+}
+
+impl PreprocessingStep {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UnicodeNormalize { .. } => "unicode_normalize",
+            Self::StripAccents => "strip_accents",
+            Self::FoldFullWidth => "fold_full_width",
+            Self::RemoveControlChars => "remove_control_chars",
+            Self::RemoveZeroWidthChars => "remove_zero_width_chars",
+            Self::Lowercase => "lowercase",
+            Self::RemoveSpecialChars => "remove_special_chars",
+            Self::StripHtml => "strip_html",
+            Self::DecodeHtmlEntities => "decode_html_entities",
+            Self::StripMarkdown => "strip_markdown",
+            Self::StripUrls { .. } => "strip_urls",
+            Self::MaskEmails { .. } => "mask_emails",
+            Self::CollapseWhitespace => "collapse_whitespace",
+            Self::Trim => "trim",
+            Self::Truncate { .. } => "truncate",
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::UnicodeNormalize { form: UnicodeForm::None } => {
+                bail!("unicode_normalize needs a form (nfc, nfd, nfkc, nfkd)")
+            }
+            Self::Truncate { max_chars: 0 } => bail!("truncate needs max_chars > 0"),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            Self::UnicodeNormalize { form } => normalizer::normalize_unicode(text, *form),
+            Self::StripAccents => normalizer::strip_accents(text),
+            Self::FoldFullWidth => normalizer::fold_full_width(text),
+            Self::RemoveControlChars => normalizer::remove_control_characters(text),
+            Self::RemoveZeroWidthChars => normalizer::remove_zero_width_characters(text),
+            Self::Lowercase => text.to_lowercase(),
+            Self::RemoveSpecialChars => normalizer::remove_special_characters(text),
+            Self::StripHtml => normalizer::decode_html_entities(&normalizer::strip_html_tags(text)),
+            Self::DecodeHtmlEntities => normalizer::decode_html_entities(text),
+            Self::StripMarkdown => normalizer::strip_markdown(text),
+            Self::StripUrls { replacement } => normalizer::replace_urls(text, replacement),
+            Self::MaskEmails { mask } => normalizer::mask_emails(text, mask),
+            Self::CollapseWhitespace => {
+                normalizer::remove_extra_spaces(&normalizer::normalize_whitespace(text))
+            }
+            Self::Trim => normalizer::remove_extra_spaces(text),
+            Self::Truncate { max_chars } => truncate_chars(text, *max_chars).to_string(),
+        }
+    }
+}
+
+pub fn run_chain(text: &str, steps: &[PreprocessingStep]) -> String {
+    steps
+        .iter()
+        .fold(text.to_string(), |text, step| step.apply(&text))
+}
+
+pub fn validate_chain(name: &str, steps: &[PreprocessingStep]) -> Result<()> {
+    if steps.is_empty() {
+        bail!("Preprocessing pipeline '{}' has no steps", name);
+    }
+
+    for (index, step) in steps.iter().enumerate() {
+        step.validate().map_err(|e| {
+            anyhow::anyhow!(
+                "Preprocessing pipeline '{}', step {} ({}): {}",
+                name,
+                index + 1,
+                step.name(),
+                e
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Picks the chain for `model_name`: the pipeline named by the model entry,
+/// then `preprocessing.default_pipeline`, then the chain implied by the
+/// legacy boolean flags.
+pub fn resolve_chain<'a>(config: &'a AppConfig, model_name: &str) -> Cow<'a, [PreprocessingStep]> {
+    let pipeline = config
+        .model_info(model_name)
+        .and_then(|info| info.preprocessing.as_deref())
+        .or(config.preprocessing.default_pipeline.as_deref());
+
+    match pipeline.and_then(|name| config.preprocessing.pipelines.get(name)) {
+        Some(steps) => Cow::Borrowed(steps.as_slice()),
+        None => Cow::Owned(legacy_chain(&config.preprocessing)),
+    }
+}
+
+fn legacy_chain(config: &PreprocessingConfig) -> Vec<PreprocessingStep> {
+    let mut steps = Vec::new();
+
+    if config.remove_control_chars {
+        steps.push(PreprocessingStep::RemoveControlChars);
+    }
+    if config.remove_zero_width_chars {
+        steps.push(PreprocessingStep::RemoveZeroWidthChars);
+    }
+    if config.fold_full_width {
+        steps.push(PreprocessingStep::FoldFullWidth);
+    }
+    if config.unicode_normalization != UnicodeForm::None {
+        steps.push(PreprocessingStep::UnicodeNormalize { form: config.unicode_normalization });
+    }
+    if config.strip_accents {
+        steps.push(PreprocessingStep::StripAccents);
+    }
+    if config.lowercase {
+        steps.push(PreprocessingStep::Lowercase);
+    }
+    if config.remove_special_chars {
+        steps.push(PreprocessingStep::RemoveSpecialChars);
+    }
+    steps.push(PreprocessingStep::CollapseWhitespace);
+
+    steps
+}
+
+pub fn truncate_chars(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((byte_index, _)) => &text[..byte_index],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Vec<PreprocessingStep> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_run_chain() {
+        let steps = parse(
+            "
+- step: strip_html
+- step: mask_emails
+- step: lowercase
+- step: collapse_whitespace
+- step: truncate
+  max_chars: 20
+",
+        );
+        assert!(validate_chain("test", &steps).is_ok());
+        assert_eq!(
+            run_chain("<p>Mail  ME at a.b@example.com</p>", &steps),
+            "mail me at [email]"
+        );
+    }
+
+    #[test]
+    fn test_scraped_text_chain() {
+        let steps = parse(
+            "
+- step: strip_html
+- step: strip_markdown
+- step: strip_urls
+  replacement: \"[URL]\"
+- step: collapse_whitespace
+",
+        );
+        assert_eq!(
+            run_chain("<p>**Love** it &amp; see [here](https://x.io) or https://t.co/a?utm=1</p>", &steps),
+            "Love it & see here or [URL]"
+        );
+    }
+
+    #[test]
+    fn test_unknown_step_is_rejected() {
+        assert!(serde_yaml::from_str::<Vec<PreprocessingStep>>("- step: shout").is_err());
+    }
+
+    #[test]
+    fn test_invalid_parameters_are_rejected() {
+        let steps = parse("- step: truncate\n  max_chars: 0");
+        assert!(validate_chain("test", &steps).is_err());
+        assert!(validate_chain("empty", &[]).is_err());
+    }
+
+    #[test]
+    fn test_truncate_chars_is_utf8_safe() {
+        assert_eq!(truncate_chars("ğüşiöç", 3), "ğüş");
+        assert_eq!(truncate_chars("ab", 5), "ab");
+    }
+}
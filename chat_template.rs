@@ -0,0 +1,182 @@
+use anyhow::Result;
+use minijinja::{context, Environment, Error, ErrorKind, Value};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatTemplateSource {
+    /// `chat_template` on the model entry in config.yaml
+    Config,
+    /// `chat_template` in the model's tokenizer_config.json
+    TokenizerConfig,
+}
+
+/// Name of the template within its environment.
+const TEMPLATE_NAME: &str = "chat";
+
+/// A compiled Jinja chat template as shipped with Hugging Face tokenizers.
+#[derive(Debug, Clone)]
+pub struct ChatTemplate {
+    env: Environment<'static>,
+    origin: ChatTemplateSource,
+    bos_token: String,
+    eos_token: String,
+}
+
+impl ChatTemplate {
+    /// Compiles `source`, so syntax errors surface here rather than on
+    /// render. Keep the result around to avoid compiling per request.
+    pub fn new(
+        source: String,
+        origin: ChatTemplateSource,
+        bos_token: Option<String>,
+        eos_token: Option<String>,
+    ) -> Result<Self> {
+        let mut env = environment();
+        env.add_template_owned(TEMPLATE_NAME, source)
+            .map_err(|e| anyhow::anyhow!("Invalid chat template: {}", e))?;
+
+        Ok(Self {
+            env,
+            origin,
+            bos_token: bos_token.unwrap_or_default(),
+            eos_token: eos_token.unwrap_or_default(),
+        })
+    }
+
+    pub fn origin(&self) -> ChatTemplateSource {
+        self.origin
+    }
+
+    /// Renders `messages` into the prompt string. `extra` is merged into the
+    /// template context (e.g. `tools` or `documents`).
+    pub fn render(
+        &self,
+        messages: &[ChatMessage],
+        add_generation_prompt: bool,
+        extra: Option<&serde_json::Value>,
+    ) -> Result<String> {
+        let template = self.env.get_template(TEMPLATE_NAME)?;
+
+        let base = context! {
+            messages => messages,
+            add_generation_prompt => add_generation_prompt,
+            bos_token => &self.bos_token,
+            eos_token => &self.eos_token,
+        };
+        // Values in `base` win over anything in `extra`
+        let ctx = match extra {
+            Some(extra) => context! { ..base, ..Value::from_serialize(extra) },
+            None => base,
+        };
+
+        template
+            .render(ctx)
+            .map_err(|e| anyhow::anyhow!("Failed to render chat template: {}", e))
+    }
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    // Hugging Face renders with both on, and templates rely on it for
+    // their whitespace
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    // Templates are written against Python's Jinja: `.strip()`, `.items()`, ...
+    env.set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+    env.add_function("raise_exception", |message: String| -> Result<Value, Error> {
+        Err(Error::new(ErrorKind::InvalidOperation, message))
+    });
+    env.add_function("strftime_now", |format: String| {
+        chrono::Local::now().format(&format).to_string()
+    });
+    env
+}
+
+/// Extracts the template from a tokenizer_config.json value. Newer configs
+/// hold a list of named templates; the one named "default" is used.
+pub fn template_from_tokenizer_config(value: &serde_json::Value) -> Option<String> {
+    match value.get("chat_template")? {
+        serde_json::Value::String(template) => Some(template.clone()),
+        serde_json::Value::Array(templates) => templates
+            .iter()
+            .find(|t| t.get("name").and_then(|n| n.as_str()) == Some("default"))
+            .or_else(|| templates.first())
+            .and_then(|t| t.get("template"))
+            .and_then(|t| t.as_str())
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHATML: &str = "{% for message in messages %}{{ '<|im_start|>' + message['role'] + '\\n' + message['content'].strip() + '<|im_end|>\\n' }}{% endfor %}{% if add_generation_prompt %}{{ '<|im_start|>assistant\\n' }}{% endif %}";
+
+    fn messages() -> Vec<ChatMessage> {
+        vec![
+            ChatMessage { role: "system".into(), content: "Be brief.".into() },
+            ChatMessage { role: "user".into(), content: " Hi! ".into() },
+        ]
+    }
+
+    #[test]
+    fn test_render_chatml() {
+        let template = ChatTemplate::new(CHATML.to_string(), ChatTemplateSource::Config, None, None).unwrap();
+        let prompt = template.render(&messages(), true, None).unwrap();
+        assert_eq!(
+            prompt,
+            "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi!<|im_end|>\n<|im_start|>assistant\n"
+        );
+    }
+
+    #[test]
+    fn test_special_tokens_and_raise_exception() {
+        let source = "{% if messages[0]['role'] != 'user' %}{{ raise_exception('first message must be from the user') }}{% endif %}{{ bos_token }}{{ messages[0]['content'] }}{{ eos_token }}";
+        let template = ChatTemplate::new(
+            source.to_string(),
+            ChatTemplateSource::TokenizerConfig,
+            Some("<s>".into()),
+            Some("</s>".into()),
+        )
+        .unwrap();
+
+        let user = vec![ChatMessage { role: "user".into(), content: "hey".into() }];
+        assert_eq!(template.render(&user, false, None).unwrap(), "<s>hey</s>");
+
+        let error = template.render(&messages(), false, None).unwrap_err();
+        assert!(error.to_string().contains("first message must be from the user"));
+    }
+
+    #[test]
+    fn test_block_tags_leave_no_whitespace() {
+        let source = "{% for message in messages %}\n    {% if message['role'] == 'user' %}\n{{ message['content'] }}|\n    {% endif %}\n{% endfor %}";
+        let template = ChatTemplate::new(source.to_string(), ChatTemplateSource::Config, None, None).unwrap();
+        assert_eq!(template.render(&messages(), false, None).unwrap(), " Hi! |\n");
+    }
+
+    #[test]
+    fn test_invalid_template_is_rejected() {
+        assert!(ChatTemplate::new("{% for %}".into(), ChatTemplateSource::Config, None, None).is_err());
+    }
+
+    #[test]
+    fn test_template_from_tokenizer_config() {
+        let single = serde_json::json!({ "chat_template": "{{ x }}" });
+        assert_eq!(template_from_tokenizer_config(&single).as_deref(), Some("{{ x }}"));
+
+        let named = serde_json::json!({ "chat_template": [
+            { "name": "tool_use", "template": "tools" },
+            { "name": "default", "template": "plain" }
+        ]});
+        assert_eq!(template_from_tokenizer_config(&named).as_deref(), Some("plain"));
+    }
+}
@@ -0,0 +1,260 @@
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use super::middleware::current_request_id;
+use super::routes::{ErrorBody, ErrorResponse};
+use crate::inference::admission::{AdmissionError, RETRY_AFTER_SECS};
+use crate::inference::InferenceError;
+use crate::model::ModelError;
+use crate::preprocessing::redaction::{redact_installed, RedactionTarget};
+
+/// Code of an error response, left on the response for the metrics layer.
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorCode(pub &'static str);
+
+/// `Json` whose rejections (bad syntax, wrong fields, missing content type)
+/// come back as `invalid_parameters` error bodies instead of plain text.
+#[derive(Debug, FromRequest)]
+#[from_request(via(Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+/// Every error the API returns. Each variant has a stable `code` that
+/// clients can match on; the message is for humans and may change.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    ModelNotFound(String),
+    #[error("{0}")]
+    ModelLoading(String),
+    #[error("Input too long: {length} {unit}, the limit is {limit}")]
+    InputTooLong {
+        length: usize,
+        limit: usize,
+        unit: &'static str,
+    },
+    #[error("{0}")]
+    InvalidParameters(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{message}")]
+    QueueFull { message: String, retry_after_secs: u64 },
+    #[error("Request timed out after {0} ms")]
+    Timeout(u64),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("Rate limit exceeded, retry in {retry_after_secs} s")]
+    RateLimited { retry_after_secs: u64 },
+    #[error("The {period} quota for this API key is used up")]
+    QuotaExceeded { period: &'static str, retry_after_secs: u64 },
+    /// The full error chain, for the logs only; clients get a generic
+    /// message and the request id
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ModelNotFound(_) => "model_not_found",
+            Self::ModelLoading(_) => "model_loading",
+            Self::InputTooLong { .. } => "input_too_long",
+            Self::InvalidParameters(_) => "invalid_parameters",
+            Self::Conflict(_) => "conflict",
+            Self::QueueFull { .. } => "queue_full",
+            Self::Timeout(_) => "timeout",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::QuotaExceeded { .. } => "quota_exceeded",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::ModelNotFound(_) => StatusCode::NOT_FOUND,
+            Self::ModelLoading(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::InputTooLong { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::InvalidParameters(_) => StatusCode::BAD_REQUEST,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::QueueFull { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::RateLimited { .. } | Self::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<ModelError> for ApiError {
+    fn from(error: ModelError) -> Self {
+        match error {
+            ModelError::NotFound(_) | ModelError::NoActiveModel => Self::ModelNotFound(error.to_string()),
+            ModelError::InvalidName(_) => Self::InvalidParameters(error.to_string()),
+            ModelError::Loading { .. } => Self::ModelLoading(error.to_string()),
+        }
+    }
+}
+
+impl From<InferenceError> for ApiError {
+    fn from(error: InferenceError) -> Self {
+        match error {
+            InferenceError::InputTooLong { length, limit, unit } => Self::InputTooLong { length, limit, unit },
+            InferenceError::InvalidInput(message) => Self::InvalidParameters(message),
+            InferenceError::Timeout { timeout_ms } => Self::Timeout(timeout_ms),
+            InferenceError::ModelNotAllowed(_) => Self::Forbidden(error.to_string()),
+        }
+    }
+}
+
+impl From<AdmissionError> for ApiError {
+    fn from(error: AdmissionError) -> Self {
+        Self::QueueFull {
+            message: error.to_string(),
+            retry_after_secs: RETRY_AFTER_SECS,
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::InvalidParameters(rejection.body_text())
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        if let Some(error) = error.downcast_ref::<ModelError>() {
+            return error.clone().into();
+        }
+        if let Some(error) = error.downcast_ref::<InferenceError>() {
+            return error.clone().into();
+        }
+        if let Some(error) = error.downcast_ref::<AdmissionError>() {
+            return error.clone().into();
+        }
+
+        // Request bodies are parsed by `ApiJson`, so anything else that
+        // fails here, JSON included, is the server's own fault
+        Self::Internal(format!("{:#}", error))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let request_id = current_request_id();
+        // Messages can quote the input (e.g. a JSON value that failed to parse)
+        let message = self.to_string();
+        let logged = redact_installed(&message, &[RedactionTarget::Logs]);
+
+        if status.is_server_error() {
+            tracing::error!(code = self.code(), request_id = ?request_id, "{}", logged);
+        } else {
+            tracing::debug!(code = self.code(), request_id = ?request_id, "{}", logged);
+        }
+
+        let message = match self {
+            Self::Internal(_) => "Internal error".to_string(),
+            _ => redact_installed(&message, &[RedactionTarget::ResponseEcho, RedactionTarget::Logs]).into_owned(),
+        };
+        let body = ErrorResponse {
+            success: false,
+            error: ErrorBody {
+                code: self.code(),
+                message,
+                status: status.as_u16(),
+                request_id,
+            },
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        response.extensions_mut().insert(ErrorCode(self.code()));
+        if let Self::RateLimited { retry_after_secs }
+        | Self::QuotaExceeded { retry_after_secs, .. }
+        | Self::QueueFull { retry_after_secs, .. } = self
+        {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_errors_survive_anyhow() {
+        let error: ApiError = anyhow::Error::from(ModelError::NotFound("gpt5".into())).into();
+        assert_eq!(error.code(), "model_not_found");
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+
+        let too_long = anyhow::Error::from(InferenceError::InputTooLong { length: 10, limit: 5, unit: "chars" })
+            .context("while preprocessing");
+        let error: ApiError = too_long.into();
+        assert_eq!(error.code(), "input_too_long");
+        assert_eq!(error.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn test_deadline_maps_to_gateway_timeout() {
+        let error: ApiError = anyhow::Error::from(InferenceError::Timeout { timeout_ms: 250 }).into();
+        assert_eq!(error.code(), "timeout");
+        assert_eq!(error.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[test]
+    fn test_untyped_errors_are_internal() {
+        let error: ApiError = anyhow::anyhow!("boom").into();
+        assert_eq!(error.code(), "internal");
+        assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        // A server-side file that fails to parse isn't the client's fault
+        let corrupt = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let error: ApiError = anyhow::Error::from(corrupt).context("Usage file \"/var/lib/usage.json\" is corrupt").into();
+        assert_eq!(error.code(), "internal");
+    }
+
+    #[tokio::test]
+    async fn test_internal_details_stay_out_of_the_body() {
+        let error: ApiError = anyhow::anyhow!("Failed to read /srv/models/secret-org/model").into();
+        let body = axum::body::to_bytes(error.into_response().into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["message"], "Internal error");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json_is_an_api_error() {
+        #[derive(Debug, serde::Deserialize)]
+        struct Body {
+            #[allow(dead_code)]
+            text: String,
+        }
+
+        let request = axum::http::Request::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from("{\"txt\": 1}"))
+            .unwrap();
+        let error = ApiJson::<Body>::from_request(request, &()).await.unwrap_err();
+        assert_eq!(error.code(), "invalid_parameters");
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_rate_limited_sets_retry_after() {
+        let response = ApiError::RateLimited { retry_after_secs: 7 }.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "7");
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::header,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use super::MetricsCollector;
+
+/// Content type of the Prometheus text exposition format.
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Scrape endpoint, served on `monitoring.metrics_host:metrics_port` apart
+/// from the API. It has no authentication; the host defaults to loopback.
+pub fn metrics_router(metrics: Arc<MetricsCollector>) -> Router {
+    Router::new()
+        .route("/metrics", get(scrape))
+        .with_state(metrics)
+}
+
+/// Binds `addr` and serves the scrape endpoint in the background.
+pub async fn spawn_metrics_server(metrics: Arc<MetricsCollector>, addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Prometheus metrics listening on {}", addr);
+
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, metrics_router(metrics)).await {
+            tracing::error!("Metrics server stopped: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+async fn scrape(State(metrics): State<Arc<MetricsCollector>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+        metrics.export_prometheus(),
+    )
+}
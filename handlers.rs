@@ -1,13 +1,20 @@
 use axum::{
     extract::{State, Path},
     Json,
-    http::StatusCode,
     response::IntoResponse,
 };
 use serde_json::json;
 
-use super::{AppState, routes::*};
-use crate::inference::device::get_device_info;
+use super::{
+    auth::{check_model_access, current_caller},
+    error::{ApiError, ApiJson},
+    middleware::{current_deadline, current_queue_wait, current_request_id},
+    routes::*,
+    AppState,
+};
+use crate::inference::{device::get_device_info, InferenceError, InferenceOptions, ANY_TASK};
+use crate::monitoring::note_request_model;
+use crate::preprocessing::tokenizer::CharIndex;
 
 // Health check
 pub async fn health_check() -> impl IntoResponse {
@@ -17,111 +24,309 @@ pub async fn health_check() -> impl IntoResponse {
     }))
 }
 
+/// Options for a request pinned to `model`, carrying the request id,
+/// deadline, queue wait and the caller's model allow-list.
+fn inference_options(model: Option<String>, return_timings: bool) -> InferenceOptions {
+    InferenceOptions {
+        model,
+        deadline: current_deadline(),
+        allowed_models: current_caller()
+            .filter(|caller| !caller.models.is_empty())
+            .map(|caller| caller.models.clone()),
+        request_id: current_request_id(),
+        return_timings,
+        queue_wait: current_queue_wait(),
+    }
+}
+
+/// Resolves the model for endpoints that only need its tokenizer, within
+/// the caller's allowed models.
+async fn resolve_allowed_model(state: &AppState, requested: Option<&str>) -> Result<String, ApiError> {
+    let model = resolve_accessible_model(state, requested).await?;
+    note_request_model(&model);
+    Ok(model)
+}
+
+/// Loads the requested (or active) model if the caller may use it. A
+/// requested model is checked before it is loaded.
+async fn resolve_accessible_model(state: &AppState, requested: Option<&str>) -> Result<String, ApiError> {
+    if let Some(model) = requested {
+        check_model_access(model)?;
+    }
+    let model = state.model_manager.resolve_model(requested).await?;
+    check_model_access(&model)?;
+    Ok(model)
+}
+
 // Single prediction
 pub async fn predict(
     State(state): State<AppState>,
-    Json(request): Json<PredictRequest>,
-) -> impl IntoResponse {
+    ApiJson(request): ApiJson<PredictRequest>,
+) -> Result<Json<PredictResponse>, ApiError> {
     let start = std::time::Instant::now();
     
     tracing::info!("Prediction request received");
     
-    // Switch model if specified
-    if let Some(model_name) = &request.model {
-        if let Err(e) = state.model_manager.switch_model(model_name).await {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(PredictResponse {
-                    success: false,
-                    result: None,
-                    error: Some(format!("Failed to switch model: {}", e)),
-                })
-            );
-        }
-    }
+    let options = inference_options(request.model.clone(), request.return_timings);
     
-    // Run inference
-    match state.inference_engine.infer_single(&request.text).await {
-        Ok(result) => {
-            let latency = start.elapsed().as_millis();
-            state.metrics.record_inference(latency as u64).await;
-            
-            (
-                StatusCode::OK,
-                Json(PredictResponse {
-                    success: true,
-                    result: Some(result),
-                    error: None,
-                })
-            )
-        }
-        Err(e) => {
-            tracing::error!("Inference failed: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(PredictResponse {
-                    success: false,
-                    result: None,
-                    error: Some(e.to_string()),
-                })
-            )
-        }
-    }
+    // Run inference; a requested model is loaded without changing the active one
+    let result = state.inference_engine.infer_single(&request.text, &options).await?;
+    
+    let latency = start.elapsed().as_millis();
+    state.metrics.record_inference(&result.model_name, latency as u64, result.input_tokens, 0).await;
+    
+    Ok(Json(PredictResponse {
+        success: true,
+        result,
+    }))
 }
 
 // Batch prediction
 pub async fn predict_batch(
     State(state): State<AppState>,
-    Json(request): Json<BatchPredictRequest>,
-) -> impl IntoResponse {
+    ApiJson(request): ApiJson<BatchPredictRequest>,
+) -> Result<Json<BatchPredictResponse>, ApiError> {
     let start = std::time::Instant::now();
     
     tracing::info!("Batch prediction request received: {} items", request.texts.len());
     
     if request.texts.is_empty() {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(BatchPredictResponse {
-                success: false,
-                results: None,
-                error: Some("Empty batch".to_string()),
-            })
-        );
-    }
-    
-    match state.inference_engine.infer_batch(request.texts).await {
-        Ok(results) => {
-            let latency = start.elapsed().as_millis();
-            state.metrics.record_batch_inference(results.len() as u64, latency as u64).await;
-            
-            (
-                StatusCode::OK,
-                Json(BatchPredictResponse {
-                    success: true,
-                    results: Some(results),
-                    error: None,
-                })
-            )
-        }
-        Err(e) => {
-            tracing::error!("Batch inference failed: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(BatchPredictResponse {
-                    success: false,
-                    results: None,
-                    error: Some(e.to_string()),
-                })
-            )
+        return Err(ApiError::InvalidParameters("Empty batch".to_string()));
+    }
+    
+    let options = inference_options(request.model.clone(), request.return_timings);
+    
+    let results = state.inference_engine.infer_batch(request.texts, &options).await?;
+    
+    let latency = start.elapsed().as_millis();
+    // Routed batches can span models; the batch is labeled with the first
+    if let Some(first) = results.first() {
+        let input_tokens = results.iter().map(|result| result.input_tokens).sum();
+        state.metrics
+            .record_batch_inference(&first.model_name, results.len() as u64, latency as u64, input_tokens)
+            .await;
+    }
+    
+    let timings = request.return_timings.then(|| {
+        let mut total = options.initial_timings();
+        for item in results.iter().filter_map(|result| result.timings.as_ref()) {
+            total.merge(item);
         }
+        total
+    });
+    
+    Ok(Json(BatchPredictResponse {
+        success: true,
+        results,
+        timings,
+    }))
+}
+
+// Named-entity recognition (token classification)
+pub async fn extract_entities(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<NerRequest>,
+) -> Result<Json<NerResponse>, ApiError> {
+    let options = inference_options(request.model.clone(), request.return_timings);
+    
+    let result = state.inference_engine
+        .extract_entities(&request.text, &options, request.aggregation_strategy)
+        .await?;
+    state.metrics
+        .record_inference(&result.model_name, result.latency_ms, result.input_tokens, result.output_tokens)
+        .await;
+    
+    Ok(Json(NerResponse {
+        success: true,
+        model: result.model_name,
+        text: result.text,
+        entities: result.entities,
+        truncated_tokens: result.truncated_tokens,
+        latency_ms: result.latency_ms,
+        timings: result.timings,
+    }))
+}
+
+// Extractive question answering
+pub async fn answer_question(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<QaRequest>,
+) -> Result<Json<QaResponse>, ApiError> {
+    if request.top_k == 0 || request.max_answer_len == 0 {
+        return Err(ApiError::InvalidParameters(
+            "top_k and max_answer_len must be at least 1".to_string(),
+        ));
+    }
+    
+    let options = inference_options(request.model.clone(), request.return_timings);
+    
+    let result = state.inference_engine
+        .answer_question(&request.question, &request.context, &options, request.top_k, request.max_answer_len)
+        .await?;
+    state.metrics
+        .record_inference(&result.model_name, result.latency_ms, result.input_tokens, result.output_tokens)
+        .await;
+    
+    Ok(Json(QaResponse {
+        success: true,
+        model: result.model_name,
+        context: result.context,
+        answers: result.answers,
+        num_windows: result.num_windows,
+        latency_ms: result.latency_ms,
+        timings: result.timings,
+    }))
+}
+
+// Zero-shot classification with an NLI model
+pub async fn classify_zero_shot(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<ZeroShotRequest>,
+) -> Result<Json<ZeroShotResponse>, ApiError> {
+    let options = inference_options(request.model.clone(), request.return_timings);
+    
+    let result = state.inference_engine
+        .classify_zero_shot(
+            &request.text,
+            &request.candidate_labels,
+            &request.hypothesis_template,
+            request.multi_label,
+            &options,
+        )
+        .await?;
+    state.metrics.record_inference(&result.model_name, result.latency_ms, result.input_tokens, 0).await;
+    
+    let (labels, scores) = result.scores.into_iter()
+        .map(|scored| (scored.label, scored.score))
+        .unzip();
+    
+    Ok(Json(ZeroShotResponse {
+        success: true,
+        model: result.model_name,
+        labels,
+        scores,
+        multi_label: result.multi_label,
+        truncated_tokens: result.truncated_tokens,
+        latency_ms: result.latency_ms,
+        timings: result.timings,
+    }))
+}
+
+// Tokenize text with the resolved model's tokenizer
+pub async fn tokenize(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<TokenizeRequest>,
+) -> Result<Json<TokenizeResponse>, ApiError> {
+    state.inference_engine.check_input_length(&request.text)?;
+    if let Some(pair) = &request.text_pair {
+        state.inference_engine.check_input_length(pair)?;
     }
+    
+    let options = inference_options(request.model.clone(), false);
+    let (model, _) = state.inference_engine.route_input(&request.text, &options, ANY_TASK).await?;
+    
+    let (text, text_pair) = if request.preprocess {
+        let engine = &state.inference_engine;
+        (
+            engine.preprocess_for_model(&model, &request.text)?,
+            request.text_pair.as_deref().map(|pair| engine.preprocess_for_model(&model, pair)).transpose()?,
+        )
+    } else {
+        (request.text.clone(), request.text_pair.clone())
+    };
+    
+    let tokenizer = state.model_manager.get_tokenizer(&model);
+    let full = tokenizer.encode_untruncated(&text, text_pair.as_deref())?;
+    let encoded = match &text_pair {
+        Some(pair) => tokenizer.encode_pair(&text, pair)?,
+        None => tokenizer.encode_input(&text)?,
+    };
+    
+    let max_length = tokenizer.max_length()
+        .map_or(state.config.inference.max_length, |limit| limit.min(state.config.inference.max_length));
+    
+    // Offsets are relative to the sequence each token came from
+    let first = CharIndex::new(&text);
+    let second = text_pair.as_deref().map(CharIndex::new);
+    let offsets = encoded.offsets.iter().zip(&encoded.sequence_ids)
+        .map(|(&offset, sequence)| match (sequence, &second) {
+            (Some(1), Some(second)) => second.char_range(offset),
+            _ => first.char_range(offset),
+        })
+        .collect();
+    
+    Ok(Json(TokenizeResponse {
+        success: true,
+        model,
+        text,
+        text_pair,
+        ids: encoded.ids,
+        tokens: encoded.tokens,
+        offsets,
+        sequence_ids: encoded.sequence_ids,
+        special_tokens_mask: encoded.special_tokens_mask,
+        num_tokens: full.len(),
+        max_length,
+        exceeds_max_length: full.len() > max_length,
+    }))
+}
+
+// Turn token ids back into text
+pub async fn detokenize(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<DetokenizeRequest>,
+) -> Result<Json<DetokenizeResponse>, ApiError> {
+    let model = resolve_allowed_model(&state, request.model.as_deref()).await?;
+    
+    let tokenizer = state.model_manager.get_tokenizer(&model);
+    if !tokenizer.has_vocabulary() {
+        return Err(ApiError::InvalidParameters(format!(
+            "Model {} has no tokenizer vocabulary, so its ids can't be decoded",
+            model
+        )));
+    }
+    let text = tokenizer.decode_with(&request.ids, request.skip_special_tokens)?;
+    
+    Ok(Json(DetokenizeResponse {
+        success: true,
+        model,
+        text,
+    }))
+}
+
+// Render a chat prompt with the model's template (debugging aid)
+pub async fn render_chat_template(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<ChatTemplateRequest>,
+) -> Result<Json<ChatTemplateResponse>, ApiError> {
+    let model = resolve_allowed_model(&state, request.model.as_deref()).await?;
+    
+    let template = state.model_manager.get_chat_template(&model)?
+        .ok_or_else(|| InferenceError::InvalidInput(format!("Model {} has no chat template", model)))?;
+    // Render failures come from the messages (e.g. raise_exception in the template)
+    let prompt = template.render(&request.messages, request.add_generation_prompt, request.context.as_ref())
+        .map_err(|e| InferenceError::InvalidInput(e.to_string()))?;
+    
+    // The template already inserts special tokens, so count without adding more
+    let (ids, _) = state.model_manager.get_tokenizer(&model).encode_with_offsets(&prompt)?;
+    
+    Ok(Json(ChatTemplateResponse {
+        success: true,
+        model,
+        template_source: template.origin(),
+        prompt,
+        num_tokens: ids.len(),
+    }))
 }
 
 // List available models
 pub async fn list_models(State(state): State<AppState>) -> impl IntoResponse {
     let models = state.model_manager.list_models().await;
+    let available: Vec<_> = state.model_manager.catalog.entries().collect();
     Json(json!({
-        "models": models
+        "models": models,
+        "available": available,
+        "allow_unlisted_models": state.config.models.allow_unlisted_models
     }))
 }
 
@@ -142,23 +347,36 @@ pub async fn get_active_model(State(state): State<AppState>) -> impl IntoRespons
 pub async fn set_active_model(
     State(state): State<AppState>,
     Path(name): Path<String>,
-) -> impl IntoResponse {
-    match state.model_manager.switch_model(&name).await {
-        Ok(_) => (
-            StatusCode::OK,
-            Json(json!({
-                "success": true,
-                "message": format!("Model switched to: {}", name)
-            }))
-        ),
-        Err(e) => (
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "success": false,
-                "error": e.to_string()
-            }))
-        )
-    }
+) -> Result<impl IntoResponse, ApiError> {
+    check_model_access(&name)?;
+    state.model_manager.switch_model(&name).await?;
+    
+    Ok(Json(json!({
+        "success": true,
+        "message": format!("Model switched to: {}", name)
+    })))
+}
+
+// Usage and quotas of the calling key
+pub async fn get_usage(State(state): State<AppState>) -> impl IntoResponse {
+    // Without authentication there is no key to count against
+    let Some(caller) = current_caller() else {
+        return Json(json!({
+            "key_id": null,
+            "rate_limiting": state.rate_limiter.enabled(),
+            "usage": null,
+            "quotas": null
+        }));
+    };
+    let usage = state.rate_limiter.usage.get(&caller.id, chrono::Utc::now());
+    let quotas = state.rate_limiter.quotas(&caller);
+    
+    Json(json!({
+        "key_id": caller.id,
+        "rate_limiting": state.rate_limiter.enabled(),
+        "usage": usage,
+        "quotas": quotas
+    }))
 }
 
 // Get model statistics
@@ -189,3 +407,206 @@ pub async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
     let metrics = state.metrics.get_summary().await;
     Json(metrics)
 }
+
+// Cache statistics
+pub async fn get_cache_stats(State(state): State<AppState>) -> impl IntoResponse {
+    let stats = state.model_manager.cache.stats();
+    Json(json!({
+        "enabled": state.config.cache.enable,
+        "cache": stats
+    }))
+}
+
+// Flush the whole cache
+pub async fn clear_cache(State(state): State<AppState>) -> impl IntoResponse {
+    let removed = state.model_manager.cache.clear();
+    tracing::info!("Cache cleared: {} entries removed", removed);
+    Json(json!({
+        "success": true,
+        "removed": removed
+    }))
+}
+
+// Current log filter
+pub async fn get_log_level(State(state): State<AppState>) -> Result<Json<LogLevelResponse>, ApiError> {
+    Ok(Json(LogLevelResponse {
+        success: true,
+        level: state.log_level.current()?,
+    }))
+}
+
+// Swap the log filter without a restart
+pub async fn set_log_level(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<LogLevelRequest>,
+) -> Result<Json<LogLevelResponse>, ApiError> {
+    let filter = tracing_subscriber::EnvFilter::try_new(&request.level)
+        .map_err(|e| ApiError::InvalidParameters(format!("Invalid log level '{}': {}", request.level, e)))?;
+    state.log_level.set(filter)?;
+    
+    let level = state.log_level.current()?;
+    tracing::info!("Log level changed to {}", level);
+    
+    Ok(Json(LogLevelResponse {
+        success: true,
+        level,
+    }))
+}
+
+// Flush the cache entries of one model
+pub async fn clear_model_cache(
+    State(state): State<AppState>,
+    Path(model): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    check_model_access(&model)?;
+    
+    let removed = state.model_manager.cache.clear_model(&model);
+    tracing::info!("Cache cleared for model {}: {} entries removed", model, removed);
+    Ok(Json(json!({
+        "success": true,
+        "model": model,
+        "removed": removed
+    })))
+}
+
+// Pre-populate the cache from a JSONL body of `{"text": ..., "model": ...}` lines
+pub async fn warmup_cache(
+    State(state): State<AppState>,
+    body: String,
+) -> Result<Json<WarmupResponse>, ApiError> {
+    if !state.config.cache.enable {
+        return Err(ApiError::Conflict("Cache is disabled".to_string()));
+    }
+
+    let mut response = WarmupResponse {
+        success: true,
+        lines: 0,
+        warmed: 0,
+        already_cached: 0,
+        failed: 0,
+        errors: Vec::new(),
+    };
+
+    for (line, entry) in warmup_lines(&body) {
+        let outcome: Result<bool, ApiError> = async {
+            let entry = entry?;
+            // Each line may name a different model, so each is checked
+            let model = resolve_accessible_model(&state, entry.model.as_deref()).await?;
+            state.inference_engine.check_input_length(&entry.text)?;
+            Ok(state.inference_engine.warm_cache(&model, &entry.text).await?)
+        }.await;
+
+        record_warmup(&mut response, line, outcome);
+    }
+
+    tracing::info!(
+        "Cache warm-up finished: {} lines, {} warmed, {} already cached, {} failed",
+        response.lines,
+        response.warmed,
+        response.already_cached,
+        response.failed
+    );
+
+    response.success = response.failed == 0;
+    Ok(Json(response))
+}
+
+/// Errors listed in a warm-up response; the rest are only counted.
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// The non-empty lines of a warm-up body, numbered from 1, each parsed.
+fn warmup_lines(body: &str) -> impl Iterator<Item = (usize, Result<WarmupLine, ApiError>)> + '_ {
+    body.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line, text)| {
+            let entry = serde_json::from_str(text).map_err(|e| ApiError::InvalidParameters(e.to_string()));
+            (line, entry)
+        })
+}
+
+/// Counts one line's outcome; `Ok(false)` means it was already cached.
+fn record_warmup(response: &mut WarmupResponse, line: usize, outcome: Result<bool, ApiError>) {
+    response.lines += 1;
+    match outcome {
+        Ok(true) => response.warmed += 1,
+        Ok(false) => response.already_cached += 1,
+        Err(e) => {
+            response.failed += 1;
+            if response.errors.len() < MAX_REPORTED_ERRORS {
+                response.errors.push(WarmupError {
+                    line,
+                    code: e.code(),
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_response() -> WarmupResponse {
+        WarmupResponse {
+            success: true,
+            lines: 0,
+            warmed: 0,
+            already_cached: 0,
+            failed: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Warms every line that parses, as the handler would with a cold cache.
+    fn warm(body: &str) -> WarmupResponse {
+        let mut response = empty_response();
+        for (line, entry) in warmup_lines(body) {
+            record_warmup(&mut response, line, entry.map(|_| true));
+        }
+        response
+    }
+
+    #[test]
+    fn test_warmup_counts_mixed_lines() {
+        let body = "{\"text\": \"hello\"}\n\nnot json\n  {\"text\": \"hi\", \"model\": \"bert-base-uncased\"}  \n{\"model\": \"x\"}\n";
+        let response = warm(body);
+
+        assert_eq!(response.lines, 4);
+        assert_eq!(response.warmed, 2);
+        assert_eq!(response.failed, 2);
+        // Line numbers count the blank line, so they match the body
+        let lines: Vec<usize> = response.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![3, 5]);
+        assert!(response.errors.iter().all(|e| e.code == "invalid_parameters"));
+    }
+
+    #[test]
+    fn test_warmup_entries_keep_their_model() {
+        let entries: Vec<WarmupLine> = warmup_lines("{\"text\": \"a\", \"model\": \"m\"}\n{\"text\": \"b\"}")
+            .map(|(_, entry)| entry.unwrap())
+            .collect();
+        assert_eq!(entries[0].model.as_deref(), Some("m"));
+        assert_eq!(entries[1].model, None);
+    }
+
+    #[test]
+    fn test_warmup_caps_reported_errors() {
+        let body = "oops\n".repeat(MAX_REPORTED_ERRORS + 5);
+        let response = warm(&body);
+
+        assert_eq!(response.failed, MAX_REPORTED_ERRORS + 5);
+        assert_eq!(response.errors.len(), MAX_REPORTED_ERRORS);
+        assert_eq!(response.errors.last().unwrap().line, MAX_REPORTED_ERRORS);
+    }
+
+    #[test]
+    fn test_already_cached_is_not_a_failure() {
+        let mut response = empty_response();
+        record_warmup(&mut response, 1, Ok(false));
+        assert_eq!(response.already_cached, 1);
+        assert_eq!(response.failed, 0);
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageTag {
+    /// ISO 639-1 code where one exists, ISO 639-3 otherwise
+    pub code: String,
+    pub confidence: f64,
+    pub reliable: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguageConfig {
+    #[serde(default)]
+    pub detect: bool,
+    /// Detections below this confidence are treated as unknown for routing
+    #[serde(default = "default_min_confidence")]
+    pub min_confidence: f64,
+    #[serde(default)]
+    pub routing: LanguageRouting,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LanguageRouting {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Language code -> model name
+    #[serde(default)]
+    pub rules: HashMap<String, String>,
+    /// Model for languages without a rule; the active model is used if unset
+    #[serde(default)]
+    pub fallback: Option<String>,
+}
+
+fn default_min_confidence() -> f64 {
+    0.5
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        Self {
+            detect: false,
+            min_confidence: default_min_confidence(),
+            routing: LanguageRouting::default(),
+        }
+    }
+}
+
+pub fn detect_language(text: &str) -> Option<LanguageTag> {
+    let info = whatlang::detect(text)?;
+
+    Some(LanguageTag {
+        code: iso_639_1(info.lang().code()).to_string(),
+        confidence: info.confidence(),
+        reliable: info.is_reliable(),
+    })
+}
+
+/// Picks the model for a detected language, or `None` to keep the active model.
+/// A rule or fallback only applies if `serves_task` accepts its model, so
+/// requests are never routed to a model built for another task.
+pub fn route<'a>(
+    language: Option<&LanguageTag>,
+    config: &'a LanguageConfig,
+    serves_task: impl Fn(&str) -> bool,
+) -> Option<&'a str> {
+    if !config.routing.enabled {
+        return None;
+    }
+
+    language
+        .filter(|tag| tag.confidence >= config.min_confidence)
+        .and_then(|tag| config.routing.rules.get(&tag.code))
+        .map(String::as_str)
+        .filter(|model| serves_task(model))
+        .or_else(|| config.routing.fallback.as_deref().filter(|model| serves_task(model)))
+}
+
+pub fn iso_639_1(code: &str) -> &str {
+    match code {
+        "eng" => "en",
+        "tur" => "tr",
+        "deu" => "de",
+        "fra" => "fr",
+        "spa" => "es",
+        "ita" => "it",
+        "por" => "pt",
+        "nld" => "nl",
+        "swe" => "sv",
+        "dan" => "da",
+        "nob" => "nb",
+        "fin" => "fi",
+        "pol" => "pl",
+        "ces" => "cs",
+        "slk" => "sk",
+        "hun" => "hu",
+        "ron" => "ro",
+        "ell" => "el",
+        "bul" => "bg",
+        "rus" => "ru",
+        "ukr" => "uk",
+        "ara" => "ar",
+        "heb" => "he",
+        "pes" => "fa",
+        "hin" => "hi",
+        "ben" => "bn",
+        "urd" => "ur",
+        "jpn" => "ja",
+        "kor" => "ko",
+        "cmn" => "zh",
+        "vie" => "vi",
+        "tha" => "th",
+        "ind" => "id",
+        "aze" => "az",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routing_config() -> LanguageConfig {
+        LanguageConfig {
+            detect: true,
+            min_confidence: 0.5,
+            routing: LanguageRouting {
+                enabled: true,
+                rules: HashMap::from([("tr".to_string(), "bert-turkish".to_string())]),
+                fallback: Some("xlm-roberta".to_string()),
+            },
+        }
+    }
+
+    fn tag(code: &str, confidence: f64) -> LanguageTag {
+        LanguageTag { code: code.to_string(), confidence, reliable: true }
+    }
+
+    #[test]
+    fn test_detect_english() {
+        let tag = detect_language("The delivery was fast and the product works exactly as described.").unwrap();
+        assert_eq!(tag.code, "en");
+    }
+
+    fn any_task(_: &str) -> bool {
+        true
+    }
+
+    #[test]
+    fn test_route_by_rule_and_fallback() {
+        let config = routing_config();
+        assert_eq!(route(Some(&tag("tr", 0.9)), &config, any_task), Some("bert-turkish"));
+        assert_eq!(route(Some(&tag("de", 0.9)), &config, any_task), Some("xlm-roberta"));
+        assert_eq!(route(Some(&tag("tr", 0.1)), &config, any_task), Some("xlm-roberta"));
+        assert_eq!(route(None, &config, any_task), Some("xlm-roberta"));
+    }
+
+    #[test]
+    fn test_route_skips_models_for_other_tasks() {
+        let config = routing_config();
+        let not_turkish = |model: &str| model != "bert-turkish";
+        assert_eq!(route(Some(&tag("tr", 0.9)), &config, not_turkish), Some("xlm-roberta"));
+        assert_eq!(route(Some(&tag("tr", 0.9)), &config, |_: &str| false), None);
+    }
+
+    #[test]
+    fn test_route_disabled() {
+        let mut config = routing_config();
+        config.routing.enabled = false;
+        assert_eq!(route(Some(&tag("tr", 0.9)), &config, any_task), None);
+    }
+}
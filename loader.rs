@@ -2,14 +2,16 @@ use anyhow::{Result, Context};
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use hf_hub::{api::sync::Api, Repo, RepoType};
-use tracing::{info, warn, error};
+use tracing::{info, warn, error, Instrument};
+
+use super::catalog::CatalogEntry;
 
 pub async fn load_model(
-    model_name: &str,
+    entry: &CatalogEntry,
     cache_dir: &Path,
     auto_download: bool,
 ) -> Result<PathBuf> {
-    let model_path = cache_dir.join(model_name);
+    let model_path = entry.cache_path(cache_dir);
     
     // Check if model exists in cache
     if model_path.exists() {
@@ -18,16 +20,27 @@ pub async fn load_model(
     }
     
     if !auto_download {
-        anyhow::bail!("Model not found and auto_download is disabled");
+        anyhow::bail!("Model {} not found and auto_download is disabled", entry.name);
     }
     
-    info!("Downloading model from Hugging Face: {}", model_name);
-    download_model_from_hf(model_name, &model_path).await?;
+    info!(
+        "Downloading model {} from Hugging Face: {}@{}",
+        entry.name,
+        entry.repo,
+        entry.revision
+    );
+    let span = tracing::info_span!(
+        "model_download",
+        model = %entry.name,
+        repo = %entry.repo,
+        revision = %entry.revision,
+    );
+    download_model_from_hf(&entry.repo, &entry.revision, &model_path).instrument(span).await?;
     
     Ok(model_path)
 }
 
-async fn download_model_from_hf(model_name: &str, dest_path: &Path) -> Result<()> {
+async fn download_model_from_hf(repo_id: &str, revision: &str, dest_path: &Path) -> Result<()> {
     // Create cache directory
     fs::create_dir_all(dest_path).await
         .context("Failed to create cache directory")?;
@@ -36,12 +49,13 @@ async fn download_model_from_hf(model_name: &str, dest_path: &Path) -> Result<()
     let api = Api::new()
         .context("Failed to initialize Hugging Face API")?;
     
-    let repo = api.repo(Repo::new(
-        model_name.to_string(),
+    let repo = api.repo(Repo::with_revision(
+        repo_id.to_string(),
         RepoType::Model,
+        revision.to_string(),
     ));
     
-    info!("Downloading model files for: {}", model_name);
+    info!("Downloading model files for: {}", repo_id);
     
     // Download required files
     let files_to_download = vec![
@@ -49,7 +63,10 @@ async fn download_model_from_hf(model_name: &str, dest_path: &Path) -> Result<()
         "model.safetensors",
         "tokenizer.json",
         "tokenizer_config.json",
+        "special_tokens_map.json",
         "vocab.txt",
+        "vocab.json",
+        "merges.txt",
     ];
     
     for file in files_to_download {
@@ -66,7 +83,7 @@ async fn download_model_from_hf(model_name: &str, dest_path: &Path) -> Result<()
         }
     }
     
-    info!("Model download completed: {}", model_name);
+    info!("Model download completed: {}", repo_id);
     Ok(())
 }
 
@@ -89,96 +106,22 @@ pub struct ModelMetadata {
     pub hidden_size: Option<usize>,
     pub num_attention_heads: Option<usize>,
     pub num_hidden_layers: Option<usize>,
+    pub pad_token_id: Option<usize>,
+    pub type_vocab_size: Option<usize>,
+    #[serde(default)]
+    pub architectures: Vec<String>,
+    #[serde(default)]
+    pub id2label: std::collections::HashMap<String, String>,
 }
-use anyhow::{Result, Context};
-use std::path::{Path, PathBuf};
-use tokio::fs;
-use hf_hub::{api::sync::Api, Repo, RepoType};
-use tracing::{info, warn, error};
 
-pub async fn load_model(
-    model_name: &str,
-    cache_dir: &Path,
-    auto_download: bool,
-) -> Result<PathBuf> {
-    let model_path = cache_dir.join(model_name);
-    
-    // Check if model exists in cache
-    if model_path.exists() {
-        info!("Model found in cache: {:?}", model_path);
-        return Ok(model_path);
-    }
-    
-    if !auto_download {
-        anyhow::bail!("Model not found and auto_download is disabled");
+impl ModelMetadata {
+    /// Labels ordered by class id, from `id2label`.
+    pub fn labels(&self) -> Vec<String> {
+        let mut labels: Vec<(usize, &String)> = self.id2label
+            .iter()
+            .filter_map(|(id, label)| id.parse().ok().map(|id| (id, label)))
+            .collect();
+        labels.sort_by_key(|(id, _)| *id);
+        labels.into_iter().map(|(_, label)| label.clone()).collect()
     }
-    
-    info!("Downloading model from Hugging Face: {}", model_name);
-    download_model_from_hf(model_name, &model_path).await?;
-    
-    Ok(model_path)
-}
-
-async fn download_model_from_hf(model_name: &str, dest_path: &Path) -> Result<()> {
-    // Create cache directory
-    fs::create_dir_all(dest_path).await
-        .context("Failed to create cache directory")?;
-    
-    // Download using hf-hub
-    let api = Api::new()
-        .context("Failed to initialize Hugging Face API")?;
-    
-    let repo = api.repo(Repo::new(
-        model_name.to_string(),
-        RepoType::Model,
-    ));
-    
-    info!("Downloading model files for: {}", model_name);
-    
-    // Download required files
-    let files_to_download = vec![
-        "config.json",
-        "model.safetensors",
-        "tokenizer.json",
-        "tokenizer_config.json",
-        "vocab.txt",
-    ];
-    
-    for file in files_to_download {
-        match repo.get(file) {
-            Ok(path) => {
-                let dest = dest_path.join(file);
-                fs::copy(&path, &dest).await
-                    .with_context(|| format!("Failed to copy {}", file))?;
-                info!("Downloaded: {}", file);
-            }
-            Err(e) => {
-                warn!("Optional file {} not found: {}", file, e);
-            }
-        }
-    }
-    
-    info!("Model download completed: {}", model_name);
-    Ok(())
-}
-
-pub fn get_model_info(model_path: &Path) -> Result<ModelMetadata> {
-    let config_path = model_path.join("config.json");
-    
-    if !config_path.exists() {
-        anyhow::bail!("Model config not found");
-    }
-    
-    let config_content = std::fs::read_to_string(&config_path)?;
-    let metadata: ModelMetadata = serde_json::from_str(&config_content)?;
-    
-    Ok(metadata)
-}
-
-#[derive(Debug, serde::Deserialize)]
-pub struct ModelMetadata {
-    pub model_type: Option<String>,
-    pub hidden_size: Option<usize>,
-    pub num_attention_heads: Option<usize>,
-    pub num_hidden_layers: Option<usize>,
 }
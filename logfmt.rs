@@ -0,0 +1,174 @@
+use std::fmt::{self, Write as _};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::{
+    format::Writer,
+    FmtContext, FormatEvent, FormatFields, FormattedFields,
+};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Formats events as logfmt lines:
+///
+/// `ts=2024-05-01T12:00:00.000Z level=info target=transformer_forge msg="Server started" request_id=3f2a`
+///
+/// Fields of the enclosing spans follow the event's own, outermost span
+/// first. Use it as both the event and the field formatter so span fields
+/// come out in the same form.
+pub struct Logfmt;
+
+impl<S, N> FormatEvent<S, N> for Logfmt
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let metadata = event.metadata();
+        write!(
+            writer,
+            "ts={} level={} target={}",
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            metadata.level().as_str().to_ascii_lowercase(),
+            metadata.target(),
+        )?;
+
+        let mut visitor = FieldVisitor::new(writer.by_ref());
+        event.record(&mut visitor);
+        visitor.result?;
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                if let Some(fields) = extensions.get::<FormattedFields<N>>() {
+                    writer.write_str(fields)?;
+                }
+            }
+        }
+
+        writeln!(writer)
+    }
+}
+
+impl<'w> FormatFields<'w> for Logfmt {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'w>, fields: R) -> fmt::Result {
+        let mut visitor = FieldVisitor::new(writer);
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+/// Writes each field as ` key=value`.
+struct FieldVisitor<'w> {
+    writer: Writer<'w>,
+    result: fmt::Result,
+}
+
+impl<'w> FieldVisitor<'w> {
+    fn new(writer: Writer<'w>) -> Self {
+        Self { writer, result: Ok(()) }
+    }
+
+    fn write(&mut self, field: &Field, value: &str) {
+        if self.result.is_err() {
+            return;
+        }
+        let key = match field.name() {
+            "message" => "msg",
+            name => name,
+        };
+        self.result = write!(self.writer, " {}=", key).and_then(|_| write_value(&mut self.writer, value));
+    }
+}
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.write(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.write(field, &format!("{:?}", value));
+    }
+}
+
+/// Quotes values that are empty or contain spaces, quotes, `=` or control
+/// characters.
+fn write_value(writer: &mut impl fmt::Write, value: &str) -> fmt::Result {
+    let needs_quotes = value.is_empty()
+        || value.chars().any(|c| c == ' ' || c == '"' || c == '=' || c == '\\' || c.is_control());
+    if !needs_quotes {
+        return writer.write_str(value);
+    }
+
+    writer.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => writer.write_str("\\\"")?,
+            '\\' => writer.write_str("\\\\")?,
+            '\n' => writer.write_str("\\n")?,
+            '\r' => writer.write_str("\\r")?,
+            '\t' => writer.write_str("\\t")?,
+            c => writer.write_char(c)?,
+        }
+    }
+    writer.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_events_are_key_value_lines() {
+        let capture = Capture::default();
+        let subscriber = tracing_subscriber::fmt()
+            .event_format(Logfmt)
+            .fmt_fields(Logfmt)
+            .with_writer({
+                let capture = capture.clone();
+                move || capture.clone()
+            })
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _request = tracing::info_span!("request", request_id = "abc-123", path = "/predict").entered();
+            tracing::warn!(model = "bert-base-uncased", latency_ms = 12, "Slow inference");
+        });
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        let line = output.lines().next().unwrap();
+        assert!(line.starts_with("ts="));
+        assert!(line.contains(" level=warn "));
+        assert!(line.ends_with(
+            "msg=\"Slow inference\" model=bert-base-uncased latency_ms=12 request_id=abc-123 path=/predict"
+        ));
+    }
+
+    #[test]
+    fn test_values_are_quoted_when_needed() {
+        let quote = |value: &str| {
+            let mut out = String::new();
+            write_value(&mut out, value).unwrap();
+            out
+        };
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote(""), "\"\"");
+        assert_eq!(quote("a=b"), "\"a=b\"");
+        assert_eq!(quote("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+    }
+}
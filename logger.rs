@@ -1,38 +1,259 @@
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use anyhow::{Context, Result};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{self as sdktrace, Sampler, Tracer},
+    Resource,
+};
+use std::time::Duration;
+use tracing_appender::non_blocking::WorkerGuard;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
-use crate::config::AppConfig;
+use tracing_subscriber::{
+    fmt::MakeWriter,
+    layer::{Layered, SubscriberExt},
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter, Layer, Registry,
+};
+use super::logfmt::Logfmt;
+use super::rotation::SizeRotatingWriter;
+use crate::config::{AppConfig, LogFormat, LogRotation, MonitoringConfig, OtlpConfig, OtlpProtocol};
 
-pub fn init_logger(config: &AppConfig) {
-    let log_level = &config.monitoring.log_level;
-    let log_file = &config.monitoring.log_file;
+/// How long the exporter waits on the collector per batch.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Every layer sits on the reloadable level filter.
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type BoxedLayer = Box<dyn Layer<Filtered> + Send + Sync>;
+
+/// Keeps logging running. Dropping it flushes and stops the file writer,
+/// so hold it until the process exits.
+pub struct Logging {
+    pub level: LogLevelHandle,
+    _file_guard: Option<WorkerGuard>,
+}
+
+/// Reads and swaps the level filter of the running subscriber.
+#[derive(Clone)]
+pub struct LogLevelHandle {
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogLevelHandle {
+    pub fn current(&self) -> Result<String> {
+        Ok(self.handle.with_current(|filter| filter.to_string())?)
+    }
+
+    pub fn set(&self, filter: EnvFilter) -> Result<()> {
+        Ok(self.handle.reload(filter)?)
+    }
+}
+
+pub fn init_logger(config: &AppConfig) -> Result<Logging> {
+    let monitoring = &config.monitoring;
+    let log_level = &monitoring.log_level;
     
-    // File appender
-    let file_appender = RollingFileAppender::new(
-        Rotation::DAILY,
-        log_file.parent().unwrap_or_else(|| std::path::Path::new("./")),
-        log_file.file_name().unwrap_or_else(|| std::ffi::OsStr::new("app.log")),
-    );
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(log_level))
+        .with_context(|| format!("Invalid log_level '{}'", log_level))?;
+    let (filter, handle) = reload::Layer::new(filter);
     
-    let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
+    let mut layers: Vec<BoxedLayer> = vec![fmt_layer(monitoring.stdout_format(), std::io::stdout, true)];
     
-    // Create layers
-    let file_layer = tracing_subscriber::fmt::layer()
-        .with_writer(non_blocking)
-        .with_ansi(false)
-        .json();
+    let file_guard = if monitoring.stdout_only {
+        None
+    } else {
+        // Writes happen on a background thread; the guard flushes it on drop
+        let (non_blocking, guard) = tracing_appender::non_blocking(file_writer(monitoring)?);
+        layers.push(fmt_layer(monitoring.file_format(), non_blocking, false));
+        Some(guard)
+    };
     
-    let stdout_layer = tracing_subscriber::fmt::layer()
-        .with_ansi(true)
-        .pretty();
+    // A collector that can't be set up costs the traces, not the logs
+    let otlp = &monitoring.otlp;
+    let otlp_error = if otlp.enabled {
+        match otlp_tracer(otlp) {
+            Ok(tracer) => {
+                layers.push(tracing_opentelemetry::layer().with_tracer(tracer).boxed());
+                None
+            }
+            Err(e) => Some(e),
+        }
+    } else {
+        None
+    };
     
     // Initialize subscriber
     tracing_subscriber::registry()
-        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-            EnvFilter::new(log_level)
-        }))
-        .with(file_layer)
-        .with(stdout_layer)
+        .with(filter)
+        .with(layers)
         .init();
     
-    tracing::info!("Logger initialized with level: {}", log_level);
+    tracing::info!(
+        "Logger initialized with level: {} ({})",
+        log_level,
+        if monitoring.stdout_only { "stdout only" } else { "stdout and file" }
+    );
+    match otlp_error {
+        Some(e) => tracing::warn!("OTLP trace export disabled: {:#}", e),
+        None if otlp.enabled => tracing::info!(
+            "Exporting traces to {} over {:?} (sampling ratio {})",
+            otlp.endpoint,
+            otlp.protocol,
+            otlp.sampling_ratio
+        ),
+        None => {}
+    }
+    
+    Ok(Logging {
+        level: LogLevelHandle { handle },
+        _file_guard: file_guard,
+    })
+}
+
+/// A formatting layer writing `format` to `writer`.
+fn fmt_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    
+    match format {
+        LogFormat::Json => layer.json().boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Logfmt => layer.event_format(Logfmt).fmt_fields(Logfmt).boxed(),
+    }
+}
+
+/// The log file, rotated per `log_rotation`.
+fn file_writer(config: &MonitoringConfig) -> Result<Box<dyn std::io::Write + Send>> {
+    let log_file = &config.log_file;
+    
+    let rotation = match config.log_rotation {
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Size => {
+            let writer = SizeRotatingWriter::new(
+                log_file,
+                config.log_max_size_mb * 1024 * 1024,
+                config.log_max_files,
+            )
+            .with_context(|| format!("Failed to open log file {:?}", log_file))?;
+            return Ok(Box::new(writer));
+        }
+    };
+    
+    // Timed files are named `<log_file>.<date>`
+    let appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(log_file.file_name().and_then(|name| name.to_str()).unwrap_or("app.log"))
+        .max_log_files(config.log_max_files)
+        .build(log_file.parent().unwrap_or_else(|| std::path::Path::new("./")))
+        .with_context(|| format!("Failed to open log file {:?}", log_file))?;
+    
+    Ok(Box::new(appender))
+}
+
+/// Batch-exporting tracer for `config`. Also installs the W3C trace-context
+/// propagator, so incoming `traceparent` headers continue the caller's trace.
+pub fn otlp_tracer(config: &OtlpConfig) -> Result<Tracer> {
+    let exporter: opentelemetry_otlp::SpanExporterBuilder = match config.protocol {
+        OtlpProtocol::Grpc => opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_endpoint(&config.endpoint)
+            .with_timeout(EXPORT_TIMEOUT)
+            .into(),
+        OtlpProtocol::Http => opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(&config.endpoint)
+            .with_timeout(EXPORT_TIMEOUT)
+            .into(),
+    };
+
+    let trace_config = sdktrace::config()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sampling_ratio))))
+        .with_resource(Resource::new([KeyValue::new("service.name", config.service_name.clone())]));
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(trace_config)
+        .install_batch(runtime::Tokio)?;
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(tracer)
+}
+
+/// Flushes spans still waiting for the collector. Blocks, so call it from
+/// a blocking thread once the server has stopped.
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::timings::{Stage, StageTimings};
+    use crate::model::cache::ModelCache;
+    use axum::{body::Bytes, Router};
+    use tokio::sync::mpsc;
+
+    /// Accepts OTLP/HTTP export requests and hands their bodies to the test.
+    async fn collector_stand_in() -> (String, mpsc::UnboundedReceiver<Bytes>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let app = Router::new().fallback(move |body: Bytes| {
+            let sender = sender.clone();
+            async move {
+                let _ = sender.send(body);
+            }
+        });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        (endpoint, receiver)
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spans_reach_the_collector() {
+        let (endpoint, mut exports) = collector_stand_in().await;
+        let config = OtlpConfig {
+            enabled: true,
+            endpoint,
+            protocol: OtlpProtocol::Http,
+            service_name: "transformer-forge-test".to_string(),
+            sampling_ratio: 1.0,
+        };
+
+        // Spans come from the real cache lookup and stage timing paths
+        let cache = ModelCache::new(10, 60);
+        let mut timings = StageTimings::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(otlp_tracer(&config).unwrap()));
+        tracing::subscriber::with_default(subscriber, || {
+            timings.time(Stage::Forward, || cache.get("bert-base-uncased", "hello"));
+        });
+        tokio::task::spawn_blocking(shutdown_tracing).await.unwrap();
+
+        let body = tokio::time::timeout(Duration::from_secs(5), exports.recv())
+            .await
+            .expect("no export within 5s")
+            .unwrap();
+        assert!(contains(&body, b"cache_lookup"));
+        assert!(contains(&body, b"bert-base-uncased"));
+        assert!(contains(&body, b"inference_stage"));
+        assert!(contains(&body, b"transformer-forge-test"));
+    }
 }
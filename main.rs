@@ -18,12 +18,16 @@ async fn main() -> Result<()> {
     let config = Arc::new(config::AppConfig::load()?);
     println!(" Configuration loaded");
     
-    // Initialize logging
-    monitoring::logger::init_logger(&config);
+    // Access logs, request spans and error messages redact with this
+    preprocessing::redaction::install(config.preprocessing.redaction.clone());
+    
+    // Initialize logging; held until exit so buffered lines reach the file
+    let logging = monitoring::logger::init_logger(&config)?;
     tracing::info!("TransformerForge starting up...");
     
     // Initialize metrics collector
-    let metrics = Arc::new(monitoring::MetricsCollector::new());
+    let catalog = model::catalog::ModelCatalog::from_config(&config.models);
+    let metrics = Arc::new(monitoring::MetricsCollector::new(catalog.entries().cloned()));
     tracing::info!("Metrics collector initialized");
     
     // Initialize model manager
@@ -36,18 +40,41 @@ async fn main() -> Result<()> {
     
     // Initialize inference engine
     let inference_engine = Arc::new(
-        inference::InferenceEngine::new(config.clone(), model_manager.clone()).await?
+        inference::InferenceEngine::new(config.clone(), model_manager.clone(), metrics.clone()).await?
     );
     tracing::info!("Inference engine initialized");
     
+    // Load API keys
+    let keys = Arc::new(api::auth::KeyStore::from_config(&config.auth)?);
+    tracing::info!(
+        "Authentication {}",
+        if config.auth.enabled { "enabled" } else { "disabled" }
+    );
+    
+    // Rate limits and quotas
+    let rate_limiter = Arc::new(api::rate_limit::RateLimiter::new(&config.rate_limit)?);
+    if config.rate_limit.enabled {
+        api::rate_limit::spawn_usage_flush(rate_limiter.clone(), std::time::Duration::from_secs(10));
+        tracing::info!("Rate limiting enabled, usage persisted to {:?}", config.rate_limit.usage_file);
+    }
+    
     // Create application state
     let app_state = api::AppState {
         config: config.clone(),
         model_manager,
         inference_engine,
         metrics,
+        keys,
+        rate_limiter: rate_limiter.clone(),
+        log_level: logging.level.clone(),
     };
     
+    // Prometheus scrape endpoint on its own port
+    if config.monitoring.enable_metrics {
+        let metrics_addr = format!("{}:{}", config.monitoring.metrics_host, config.monitoring.metrics_port);
+        monitoring::exporter::spawn_metrics_server(app_state.metrics.clone(), &metrics_addr).await?;
+    }
+    
     // Create router
     let app = api::create_router(app_state);
     
@@ -57,27 +84,55 @@ async fn main() -> Result<()> {
     
     println!("======================================================");
     println!(" Server running on http://{}", addr);
-    println!(" Metrics available at http://{}:{}/metrics", 
-        config.server.host, 
-        config.monitoring.metrics_port
-    );
+    if config.monitoring.enable_metrics {
+        println!(" Metrics available at http://{}:{}/metrics", 
+            config.monitoring.metrics_host, 
+            config.monitoring.metrics_port
+        );
+    }
     println!("======================================================");
     println!("\n💡 Available endpoints:");
     println!("  POST /predict              - Single inference");
     println!("  POST /predict/batch        - Batch inference");
+    println!("  POST /ner                  - Named-entity recognition");
+    println!("  POST /qa                   - Extractive question answering");
+    println!("  POST /zero-shot            - Zero-shot classification");
+    println!("  POST /tokenize             - Tokenize text");
+    println!("  POST /detokenize           - Token ids to text");
+    println!("  POST /chat/template        - Render chat prompt");
     println!("  GET  /models               - List models");
     println!("  GET  /models/active        - Get active model");
     println!("  POST /models/:name/activate - Switch model");
     println!("  GET  /info                 - System info");
-    println!("  GET  /metrics              - Metrics summary");
+    println!("  GET  /metrics/summary      - Metrics summary (JSON)");
+    println!("  GET  /usage                - Usage and quotas for your key");
     println!("  GET  /health               - Health check");
+    println!("  GET  /admin/cache          - Cache statistics");
+    println!("  DELETE /admin/cache        - Flush cache");
+    println!("  DELETE /admin/cache/:model - Flush one model's cache");
+    println!("  POST /admin/cache/warmup   - Warm cache from JSONL");
+    println!("  GET  /admin/log-level      - Current log filter");
+    println!("  PUT  /admin/log-level      - Change log filter");
     println!("\n Ready to process requests!\n");
     
     tracing::info!("Server started on {}", addr);
     
     // Start server
-    axum::serve(listener, app)
+    // Connection info gives the rate limiter the client IP
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
         .await?;
     
+    // Usage counted since the last background flush
+    if let Err(e) = rate_limiter.usage.flush() {
+        tracing::error!("Failed to persist usage counters: {:#}", e);
+    }
+    
+    // Spans still buffered for the collector
+    tokio::task::spawn_blocking(monitoring::logger::shutdown_tracing).await?;
+    drop(logging);
+    
     Ok(())
 }
@@ -1,15 +1,33 @@
 use axum::{
     body::Body,
-    http::{Request, StatusCode},
+    extract::{MatchedPath, State},
+    http::{HeaderMap, HeaderValue, Request},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use opentelemetry::propagation::Extractor;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use super::{
+    auth::{current_caller, KeyId},
+    error::{ApiError, ErrorCode},
+    AppState,
+};
+use crate::monitoring::with_request_model;
+use crate::preprocessing::redaction::{redact_installed, RedactionTarget};
+use crate::inference::{admission::Priority, Deadline};
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const TRACEPARENT: &str = "traceparent";
+
+/// Access log: one line per request, inside the request span so it carries
+/// the request id.
 pub async fn logging_middleware(
     req: Request<Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Response {
     let start = Instant::now();
     let method = req.method().clone();
     let uri = req.uri().clone();
@@ -18,27 +36,286 @@ pub async fn logging_middleware(
     
     let latency = start.elapsed();
     let status = response.status();
+    let key_id = response.extensions().get::<KeyId>().map(|KeyId(id)| id.as_str());
     
     tracing::info!(
         method = %method,
-        uri = %uri,
-        status = %status,
-        latency_ms = latency.as_millis(),
+        uri = %redact_installed(&uri.to_string(), &[RedactionTarget::Logs]),
+        status = status.as_u16(),
+        latency_ms = latency.as_millis() as u64,
+        key_id,
         "Request processed"
     );
     
-    Ok(response)
+    response
 }
 
+/// Span for `TraceLayer`, like its default but with the URI redacted for
+/// the logs. At `info` so the default filter keeps and exports it.
+pub fn http_span(req: &Request<Body>) -> tracing::Span {
+    tracing::info_span!(
+        "http_request",
+        method = %req.method(),
+        uri = %redact_installed(&req.uri().to_string(), &[RedactionTarget::Logs]),
+        version = ?req.version(),
+    )
+}
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, if called inside `request_id_middleware`.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Takes the request id from `x-request-id`, else the trace id of a W3C
+/// `traceparent`, else a new UUID. The id is echoed back, made available to
+/// handlers and recorded on a span around the whole request.
 pub async fn request_id_middleware(
     mut req: Request<Body>,
     next: Next,
 ) -> Response {
-    let request_id = uuid::Uuid::new_v4().to_string();
-    req.headers_mut().insert(
-        "x-request-id",
-        request_id.parse().unwrap(),
+    let traceparent = req.headers().get(TRACEPARENT).cloned();
+    let request_id = req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .or_else(|| traceparent.as_ref().and_then(|value| value.to_str().ok()).and_then(trace_id))
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    // Every source yields visible ASCII, but an id that isn't a valid header
+    // is only left off the headers rather than failing the request
+    let header_value = HeaderValue::from_str(&request_id).ok();
+    if let Some(value) = &header_value {
+        req.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+    }
+    
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %redact_installed(req.uri().path(), &[RedactionTarget::Logs]),
     );
+    // Continue the caller's trace when spans are exported
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    span.set_parent(parent);
+    let mut response = REQUEST_ID.scope(request_id, next.run(req)).instrument(span).await;
     
-    next.run(req).await
+    if let Some(value) = header_value {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    if let Some(traceparent) = traceparent {
+        response.headers_mut().insert(TRACEPARENT, traceparent);
+    }
+    
+    response
+}
+
+/// Reads trace context from request headers.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Accepts client ids that are safe to echo and log.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Trace id of a `version-traceid-parentid-flags` traceparent.
+fn trace_id(traceparent: &str) -> Option<String> {
+    let mut parts = traceparent.trim().split('-');
+    let (_version, trace_id) = (parts.next()?, parts.next()?);
+    let valid = trace_id.len() == 32
+        && trace_id.bytes().all(|b| b.is_ascii_hexdigit())
+        && trace_id.bytes().any(|b| b != b'0');
+    valid.then(|| trace_id.to_ascii_lowercase())
+}
+
+tokio::task_local! {
+    static DEADLINE: Deadline;
+}
+
+/// Deadline of the request being handled, if called inside `timeout_middleware`.
+pub fn current_deadline() -> Option<Deadline> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Applies `server.request_timeout_ms`, or the `x-request-timeout-ms`
+/// header capped at `server.max_request_timeout_ms`. The deadline is made
+/// available to handlers so the engine can stop work the client will never see.
+pub async fn timeout_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let server = &state.config.server;
+    let timeout_ms = match requested_timeout_ms(&req) {
+        Ok(Some(requested)) => requested.min(server.max_request_timeout_ms.unwrap_or(server.request_timeout_ms)),
+        Ok(None) => server.request_timeout_ms,
+        Err(e) => return e.into_response(),
+    };
+    let deadline = Deadline::after(Duration::from_millis(timeout_ms));
+    let uri = req.uri().clone();
+
+    match tokio::time::timeout(deadline.remaining(), DEADLINE.scope(deadline, next.run(req))).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!(
+                uri = %redact_installed(&uri.to_string(), &[RedactionTarget::Logs]),
+                timeout_ms,
+                "Request timed out"
+            );
+            ApiError::Timeout(timeout_ms).into_response()
+        }
+    }
+}
+
+fn requested_timeout_ms(req: &Request<Body>) -> Result<Option<u64>, ApiError> {
+    let Some(value) = req.headers().get("x-request-timeout-ms") else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|&ms| ms > 0)
+        .map(Some)
+        .ok_or_else(|| ApiError::InvalidParameters(
+            "x-request-timeout-ms must be a positive number of milliseconds".to_string(),
+        ))
+}
+
+tokio::task_local! {
+    static QUEUE_WAIT: Duration;
+}
+
+/// Time the request being handled spent queued, if called inside
+/// `admission_middleware`.
+pub fn current_queue_wait() -> Option<Duration> {
+    QUEUE_WAIT.try_with(|waited| *waited).ok()
+}
+
+/// Holds the request in the admission queue until a worker slot is free.
+/// Priority comes from the `x-priority` header (`interactive` or `batch`),
+/// then the API key's `priority`; otherwise batch endpoints queue as batch
+/// work.
+pub async fn admission_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let priority = match requested_priority(&req) {
+        Ok(priority) => priority,
+        Err(e) => return e.into_response(),
+    };
+
+    let permit = match state.inference_engine.admission.acquire(priority, current_deadline()).await {
+        Ok(permit) => permit,
+        Err(e) => {
+            let error = ApiError::from(e);
+            if matches!(error, ApiError::QueueFull { .. }) {
+                state.metrics.shed_requests.inc();
+            }
+            return error.into_response();
+        }
+    };
+    state.metrics.queue_wait.observe(permit.waited.as_secs_f64() * 1000.0);
+
+    let response = QUEUE_WAIT.scope(permit.waited, next.run(req)).await;
+    drop(permit);
+    response
+}
+
+fn requested_priority(req: &Request<Body>) -> Result<Priority, ApiError> {
+    if let Some(value) = req.headers().get("x-priority") {
+        return value
+            .to_str()
+            .ok()
+            .and_then(Priority::parse)
+            .ok_or_else(|| ApiError::InvalidParameters(
+                "x-priority must be 'interactive' or 'batch'".to_string(),
+            ));
+    }
+
+    if let Some(priority) = current_caller().and_then(|caller| caller.priority) {
+        return Ok(priority);
+    }
+
+    if req.uri().path().ends_with("/batch") {
+        Ok(Priority::Batch)
+    } else {
+        Ok(Priority::Interactive)
+    }
+}
+
+/// Request count, latency and errors by route template, model and status,
+/// plus the in-flight gauge.
+pub async fn metrics_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let endpoint = req.extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+
+    state.metrics.in_flight.inc();
+    let _in_flight = InFlight(&state);
+    let (response, model) = with_request_model(next.run(req)).await;
+
+    let error_code = response.extensions().get::<ErrorCode>().map(|ErrorCode(code)| *code);
+    state.metrics.record_request(
+        &endpoint,
+        model.as_deref(),
+        response.status().as_u16(),
+        error_code,
+        start.elapsed().as_millis() as u64,
+    );
+
+    response
+}
+
+/// Decrements the in-flight gauge even if the client goes away mid-request.
+struct InFlight<'a>(&'a AppState);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.metrics.in_flight.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_id_from_traceparent() {
+        assert_eq!(
+            trace_id("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").as_deref(),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+        assert_eq!(trace_id("00-00000000000000000000000000000000-00f067aa0ba902b7-01"), None);
+        assert_eq!(trace_id("garbage"), None);
+    }
+
+    #[test]
+    fn test_request_ids_must_be_printable() {
+        assert!(is_valid_request_id("req-123"));
+        assert!(!is_valid_request_id("has space"));
+        assert!(!is_valid_request_id(&"x".repeat(200)));
+    }
 }
@@ -1,22 +1,28 @@
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{get, post, delete},
+    middleware::{from_fn, from_fn_with_state},
 };
 use tower_http::{
     trace::TraceLayer,
-    cors::{CorsLayer, Any},
+    cors::{AllowOrigin, Any, CorsLayer},
 };
 use std::sync::Arc;
 
 pub mod routes;
 pub mod handlers;
 pub mod middleware;
+pub mod error;
+pub mod auth;
+pub mod rate_limit;
 
+use auth::{Guard, KeyStore, Scope};
+use rate_limit::RateLimiter;
 use crate::{
-    config::AppConfig,
+    config::{AppConfig, ServerConfig},
     model::ModelManager,
     inference::InferenceEngine,
-    monitoring::MetricsCollector,
+    monitoring::{logger::LogLevelHandle, MetricsCollector},
 };
 
 #[derive(Clone)]
@@ -25,36 +31,101 @@ pub struct AppState {
     pub model_manager: Arc<ModelManager>,
     pub inference_engine: Arc<InferenceEngine>,
     pub metrics: Arc<MetricsCollector>,
+    pub keys: Arc<KeyStore>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub log_level: LogLevelHandle,
 }
 
 pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        // Health check
-        .route("/health", get(handlers::health_check))
-        
-        // Inference endpoints
+    let keys = state.keys.clone();
+    let timeout = from_fn_with_state(state.clone(), middleware::timeout_middleware);
+    
+    // Inference endpoints, behind the admission queue
+    let inference = Router::new()
         .route("/predict", post(handlers::predict))
         .route("/predict/batch", post(handlers::predict_batch))
-        
-        // Model management
+        .route("/ner", post(handlers::extract_entities))
+        .route("/qa", post(handlers::answer_question))
+        .route("/zero-shot", post(handlers::classify_zero_shot))
+        .route_layer(from_fn_with_state(state.clone(), middleware::admission_middleware))
+        .route_layer(from_fn_with_state(state.clone(), rate_limit::rate_limit_middleware))
+        .route_layer(from_fn_with_state(Guard::new(&keys, Some(Scope::Predict)), auth::authorize));
+    
+    // Tokenization
+    let tokenization = Router::new()
+        .route("/tokenize", post(handlers::tokenize))
+        .route("/detokenize", post(handlers::detokenize))
+        .route("/chat/template", post(handlers::render_chat_template))
+        .route_layer(from_fn_with_state(state.clone(), rate_limit::rate_limit_middleware))
+        .route_layer(from_fn_with_state(Guard::new(&keys, Some(Scope::Predict)), auth::authorize));
+    
+    // Read-only model and system info, for any valid key
+    let info = Router::new()
         .route("/models", get(handlers::list_models))
         .route("/models/active", get(handlers::get_active_model))
-        .route("/models/:name/activate", post(handlers::set_active_model))
-        .route("/models/stats", get(handlers::get_model_stats))
-        
-        // System info
         .route("/info", get(handlers::system_info))
-        .route("/metrics", get(handlers::get_metrics))
+        .route("/usage", get(handlers::get_usage))
+        .route_layer(from_fn_with_state(Guard::new(&keys, None), auth::authorize));
+    
+    let metrics = Router::new()
+        .route("/models/stats", get(handlers::get_model_stats))
+        .route("/metrics/summary", get(handlers::get_metrics))
+        .route_layer(from_fn_with_state(Guard::new(&keys, Some(Scope::Metrics)), auth::authorize));
+    
+    Router::new()
+        // Health check stays open for load balancers
+        .route("/health", get(handlers::health_check))
+        .merge(inference)
+        .merge(tokenization)
+        .merge(info)
+        .merge(metrics)
+        .merge(admin_router(&keys))
         
         // Add state
-        .with_state(state)
+        .with_state(state.clone())
         
         // Middleware
-        .layer(TraceLayer::new_for_http())
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any)
-        )
+        .layer(timeout)
+        .layer(from_fn_with_state(state.clone(), middleware::metrics_middleware))
+        .layer(TraceLayer::new_for_http().make_span_with(middleware::http_span))
+        .layer(cors_layer(&state.config.server))
+        .layer(from_fn(middleware::logging_middleware))
+        .layer(from_fn(middleware::request_id_middleware))
+}
+
+/// Routes that change server state, each group behind its admin scope.
+fn admin_router(keys: &Arc<KeyStore>) -> Router<AppState> {
+    // Model management
+    let models = Router::new()
+        .route("/models/:name/activate", post(handlers::set_active_model))
+        .route_layer(from_fn_with_state(Guard::new(keys, Some(Scope::AdminModels)), auth::authorize));
+    
+    // Cache administration
+    let cache = Router::new()
+        .route("/admin/cache", get(handlers::get_cache_stats).delete(handlers::clear_cache))
+        .route("/admin/cache/warmup", post(handlers::warmup_cache))
+        .route("/admin/cache/:model", delete(handlers::clear_model_cache))
+        .route_layer(from_fn_with_state(Guard::new(keys, Some(Scope::AdminCache)), auth::authorize));
+    
+    // Runtime log level
+    let logs = Router::new()
+        .route("/admin/log-level", get(handlers::get_log_level).put(handlers::set_log_level))
+        .route_layer(from_fn_with_state(Guard::new(keys, Some(Scope::AdminLogs)), auth::authorize));
+    
+    models.merge(cache).merge(logs)
+}
+
+fn cors_layer(config: &ServerConfig) -> CorsLayer {
+    let origins = if config.cors_allowed_origins.is_empty() {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(config.cors_allowed_origins.iter().filter_map(|origin| {
+            origin.parse().map_err(|_| tracing::warn!("Ignoring invalid CORS origin {:?}", origin)).ok()
+        }))
+    };
+    
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(Any)
+        .allow_headers(Any)
 }
@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+
+use crate::preprocessing::tokenizer::{CharIndex, EncodedInput};
+
+/// How sub-word predictions are merged into entities.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregationStrategy {
+    /// One entity per non-`O` token, sub-words included
+    None,
+    /// A word takes the label of its first sub-word
+    #[default]
+    First,
+    /// A word takes the label with the highest mean probability over its sub-words
+    Average,
+    /// A word takes the label of its most confident sub-word
+    Max,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entity {
+    /// Entity type (`ORG`, `PER`, ...), or the raw tag with `none`
+    pub entity_group: String,
+    pub score: f32,
+    /// Char offsets into the input text
+    pub start: usize,
+    pub end: usize,
+    pub word: String,
+}
+
+struct Prediction {
+    label: usize,
+    score: f32,
+    start: usize,
+    end: usize,
+}
+
+/// Turns per-token label probabilities into entity spans. `offsets` in
+/// `encoded` are byte offsets into `text`; the result uses char offsets.
+pub fn aggregate_entities(
+    text: &str,
+    encoded: &EncodedInput,
+    probabilities: &[Vec<f32>],
+    labels: &[String],
+    strategy: AggregationStrategy,
+) -> Vec<Entity> {
+    // Special tokens and empty offsets ([CLS], padding) carry no text
+    let tokens: Vec<usize> = (0..encoded.len().min(probabilities.len()))
+        .filter(|&i| encoded.special_tokens_mask.get(i) != Some(&1))
+        .filter(|&i| encoded.offsets[i].0 < encoded.offsets[i].1)
+        .collect();
+    let chars = CharIndex::new(text);
+
+    if strategy == AggregationStrategy::None {
+        return tokens
+            .iter()
+            .map(|&i| (i, argmax(&probabilities[i])))
+            .filter(|(_, (label, _))| tag(labels, *label).1.is_some())
+            .map(|(i, (label, score))| {
+                let (start, end) = encoded.offsets[i];
+                entity(text, &chars, labels[label].clone(), score, start, end)
+            })
+            .collect();
+    }
+
+    // Sub-words of the same word are adjacent and share a word id
+    let mut words: Vec<Vec<usize>> = Vec::new();
+    let mut previous_word = None;
+    for &i in &tokens {
+        let word = encoded.word_ids.get(i).copied().flatten();
+        match (word, previous_word, words.last_mut()) {
+            (Some(word), Some(previous), Some(group)) if word == previous => group.push(i),
+            _ => words.push(vec![i]),
+        }
+        previous_word = word;
+    }
+
+    let predictions: Vec<Prediction> = words
+        .iter()
+        .map(|word| {
+            let (label, score) = match strategy {
+                AggregationStrategy::Average => {
+                    let mut mean = vec![0.0; probabilities[word[0]].len()];
+                    for &i in word {
+                        for (acc, p) in mean.iter_mut().zip(&probabilities[i]) {
+                            *acc += p / word.len() as f32;
+                        }
+                    }
+                    argmax(&mean)
+                }
+                AggregationStrategy::Max => word
+                    .iter()
+                    .map(|&i| argmax(&probabilities[i]))
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .unwrap_or_default(),
+                _ => argmax(&probabilities[word[0]]),
+            };
+
+            Prediction {
+                label,
+                score,
+                start: encoded.offsets[word[0]].0,
+                end: encoded.offsets[word[word.len() - 1]].1,
+            }
+        })
+        .collect();
+
+    group_words(text, &chars, &predictions, labels)
+}
+
+/// Merges adjacent words of the same entity type; a `B-` tag always
+/// starts a new entity.
+fn group_words(text: &str, chars: &CharIndex, words: &[Prediction], labels: &[String]) -> Vec<Entity> {
+    let mut entities = Vec::new();
+    let mut current: Option<(&str, Vec<&Prediction>)> = None;
+
+    for word in words {
+        let (begin, entity_type) = tag(labels, word.label);
+
+        let continues = match (&current, entity_type) {
+            (Some((current_type, _)), Some(entity_type)) => !begin && *current_type == entity_type,
+            _ => false,
+        };
+
+        if continues {
+            if let Some((_, group)) = current.as_mut() {
+                group.push(word);
+            }
+            continue;
+        }
+
+        if let Some((entity_type, group)) = current.take() {
+            entities.push(group_entity(text, chars, entity_type, &group));
+        }
+        current = entity_type.map(|entity_type| (entity_type, vec![word]));
+    }
+
+    if let Some((entity_type, group)) = current {
+        entities.push(group_entity(text, chars, entity_type, &group));
+    }
+
+    entities
+}
+
+fn group_entity(text: &str, chars: &CharIndex, entity_type: &str, group: &[&Prediction]) -> Entity {
+    let score = group.iter().map(|word| word.score).sum::<f32>() / group.len() as f32;
+    entity(text, chars, entity_type.to_string(), score, group[0].start, group[group.len() - 1].end)
+}
+
+/// Splits an IOB tag into (is `B-`, entity type); `O` has no type.
+fn tag(labels: &[String], label: usize) -> (bool, Option<&str>) {
+    let label = labels.get(label).map(String::as_str).unwrap_or("O");
+    match label.split_once('-') {
+        _ if label == "O" => (false, None),
+        Some(("B", entity_type)) => (true, Some(entity_type)),
+        Some(("I", entity_type)) => (false, Some(entity_type)),
+        _ => (false, Some(label)),
+    }
+}
+
+fn argmax(probabilities: &[f32]) -> (usize, f32) {
+    probabilities
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or_default()
+}
+
+fn entity(text: &str, chars: &CharIndex, entity_group: String, score: f32, start: usize, end: usize) -> Entity {
+    // Byte-level BPE offsets include the leading space
+    let surface = &text[start..end];
+    let start = start + (surface.len() - surface.trim_start().len());
+    let end = end - (surface.len() - surface.trim_end().len());
+    let (char_start, char_end) = chars.char_range((start, end));
+
+    Entity {
+        entity_group,
+        score,
+        start: char_start,
+        end: char_end,
+        word: text[start..end].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels() -> Vec<String> {
+        ["O", "B-ORG", "I-ORG", "B-PER", "I-PER"].iter().map(|s| s.to_string()).collect()
+    }
+
+    // "Açme Corp hired John": [CLS] Aç ##me Corp hired John [SEP]
+    fn encoded() -> EncodedInput {
+        EncodedInput {
+            ids: vec![101, 1, 2, 3, 4, 5, 102],
+            tokens: ["[CLS]", "Aç", "##me", "Corp", "hired", "John", "[SEP]"].iter().map(|s| s.to_string()).collect(),
+            offsets: vec![(0, 0), (0, 3), (3, 5), (6, 10), (11, 16), (17, 21), (0, 0)],
+            sequence_ids: vec![None, Some(0), Some(0), Some(0), Some(0), Some(0), None],
+            word_ids: vec![None, Some(0), Some(0), Some(1), Some(2), Some(3), None],
+            attention_mask: vec![1; 7],
+            type_ids: vec![0; 7],
+            special_tokens_mask: vec![1, 0, 0, 0, 0, 0, 1],
+            overflowing: Vec::new(),
+        }
+    }
+
+    fn probabilities() -> Vec<Vec<f32>> {
+        vec![
+            vec![1.0, 0.0, 0.0, 0.0, 0.0],
+            vec![0.1, 0.8, 0.1, 0.0, 0.0], // Aç: B-ORG
+            vec![0.6, 0.0, 0.4, 0.0, 0.0], // ##me: O, but I-ORG on average
+            vec![0.1, 0.0, 0.9, 0.0, 0.0], // Corp: I-ORG
+            vec![0.9, 0.0, 0.0, 0.1, 0.0], // hired: O
+            vec![0.0, 0.0, 0.0, 0.7, 0.3], // John: B-PER
+            vec![1.0, 0.0, 0.0, 0.0, 0.0],
+        ]
+    }
+
+    const TEXT: &str = "Açme Corp hired John";
+
+    #[test]
+    fn test_first_groups_words_into_entities() {
+        let entities = aggregate_entities(TEXT, &encoded(), &probabilities(), &labels(), AggregationStrategy::First);
+
+        assert_eq!(entities.len(), 2);
+        assert_eq!(entities[0].entity_group, "ORG");
+        assert_eq!(entities[0].word, "Açme Corp");
+        assert_eq!((entities[0].start, entities[0].end), (0, 9));
+        assert!((entities[0].score - 0.85).abs() < 1e-6);
+        assert_eq!(entities[1].entity_group, "PER");
+        assert_eq!((entities[1].start, entities[1].end), (16, 20));
+    }
+
+    #[test]
+    fn test_average_and_max_scores() {
+        let average = aggregate_entities(TEXT, &encoded(), &probabilities(), &labels(), AggregationStrategy::Average);
+        // Aç/##me average: O 0.35, B-ORG 0.4, I-ORG 0.25
+        assert!((average[0].score - (0.4 + 0.9) / 2.0).abs() < 1e-6);
+
+        let max = aggregate_entities(TEXT, &encoded(), &probabilities(), &labels(), AggregationStrategy::Max);
+        assert_eq!(max[0].word, "Açme Corp");
+        assert!((max[0].score - 0.85).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_none_keeps_sub_words() {
+        let entities = aggregate_entities(TEXT, &encoded(), &probabilities(), &labels(), AggregationStrategy::None);
+        let tags: Vec<_> = entities.iter().map(|e| (e.entity_group.as_str(), e.word.as_str())).collect();
+
+        assert_eq!(tags, vec![("B-ORG", "Aç"), ("I-ORG", "Corp"), ("B-PER", "John")]);
+    }
+
+    #[test]
+    fn test_begin_tag_splits_adjacent_entities() {
+        let mut probabilities = probabilities();
+        probabilities[3] = vec![0.0, 0.9, 0.1, 0.0, 0.0]; // Corp: B-ORG
+        let entities = aggregate_entities(TEXT, &encoded(), &probabilities, &labels(), AggregationStrategy::First);
+
+        let words: Vec<_> = entities.iter().map(|e| e.word.as_str()).collect();
+        assert_eq!(words, vec!["Açme", "Corp", "John"]);
+    }
+}
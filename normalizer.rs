@@ -1,9 +1,44 @@
 use regex::Regex;
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use super::redaction::EMAIL_RE;
 
 lazy_static! {
-    static ref SPECIAL_CHARS_RE: Regex = Regex::new(r"[^a-zA-Z0-9\s]").unwrap();
+    // Letters, combining marks and digits of any script are kept
+    static ref SPECIAL_CHARS_RE: Regex = Regex::new(r"[^\p{L}\p{M}\p{N}\s]").unwrap();
     static ref WHITESPACE_RE: Regex = Regex::new(r"\s+").unwrap();
+    static ref HTML_SCRIPT_RE: Regex = Regex::new(r"(?is)<(script|style|noscript)\b[^>]*>.*?</(script|style|noscript)\s*>").unwrap();
+    static ref HTML_COMMENT_RE: Regex = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    static ref HTML_BLOCK_TAG_RE: Regex = Regex::new(
+        r"(?i)</?(p|div|br|hr|li|ul|ol|tr|td|th|table|h[1-6]|section|article|header|footer|blockquote|pre)\b[^>]*>"
+    ).unwrap();
+    static ref HTML_TAG_RE: Regex = Regex::new(r"</?[a-zA-Z][^>]*>").unwrap();
+    static ref HTML_ENTITY_RE: Regex = Regex::new(r"&(#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|[a-zA-Z][a-zA-Z0-9]{1,31});").unwrap();
+    static ref MD_CODE_FENCE_RE: Regex = Regex::new(r"(?m)^[ \t]*(```|~~~).*$").unwrap();
+    static ref MD_IMAGE_RE: Regex = Regex::new(r"!\[([^\]]*)\]\([^)]*\)").unwrap();
+    static ref MD_LINK_RE: Regex = Regex::new(r"\[([^\]]+)\]\([^)]*\)").unwrap();
+    static ref MD_HEADING_RE: Regex = Regex::new(r"(?m)^[ \t]{0,3}#{1,6}[ \t]+").unwrap();
+    static ref MD_BLOCKQUOTE_RE: Regex = Regex::new(r"(?m)^[ \t]{0,3}>[ \t]?").unwrap();
+    static ref MD_LIST_RE: Regex = Regex::new(r"(?m)^[ \t]*(?:[-*+]|\d+[.)])[ \t]+").unwrap();
+    static ref MD_RULE_RE: Regex = Regex::new(r"(?m)^[ \t]{0,3}(?:-{3,}|\*{3,}|_{3,})[ \t]*$").unwrap();
+    static ref MD_BOLD_RE: Regex = Regex::new(r"\*\*([^*]+)\*\*|__([^_]+)__").unwrap();
+    static ref MD_ITALIC_RE: Regex = Regex::new(r"\*([^*\n]+)\*|\b_([^_\n]+)_\b").unwrap();
+    static ref MD_STRIKE_RE: Regex = Regex::new(r"~~([^~]+)~~").unwrap();
+    static ref MD_INLINE_CODE_RE: Regex = Regex::new(r"`([^`]+)`").unwrap();
+    static ref URL_RE: Regex = Regex::new(r"(?i)\b(?:https?://|www\.)\S+").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnicodeForm {
+    #[default]
+    None,
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
 }
 
 pub fn remove_special_characters(text: &str) -> String {
@@ -18,12 +53,147 @@ pub fn remove_extra_spaces(text: &str) -> String {
     text.trim().to_string()
 }
 
-pub fn normalize_unicode(text: &str) -> String {
-    // Unicode normalization (NFC form)
+/// Drops markup, comments and script/style bodies while keeping the text
+/// content. Block-level tags become spaces so words don't run together.
+pub fn strip_html_tags(text: &str) -> String {
+    let text = HTML_SCRIPT_RE.replace_all(text, " ");
+    let text = HTML_COMMENT_RE.replace_all(&text, " ");
+    let text = HTML_BLOCK_TAG_RE.replace_all(&text, " ");
+    HTML_TAG_RE.replace_all(&text, "").to_string()
+}
+
+pub fn decode_html_entities(text: &str) -> String {
+    HTML_ENTITY_RE
+        .replace_all(text, |caps: &regex::Captures| {
+            let entity = &caps[1];
+            let decoded = if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = entity.strip_prefix('#') {
+                dec.parse().ok().and_then(char::from_u32)
+            } else {
+                named_entity(entity)
+            };
+
+            decoded
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| caps[0].to_string())
+        })
+        .to_string()
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "euro" => '€',
+        "pound" => '£',
+        "yen" => '¥',
+        "deg" => '°',
+        "middot" => '·',
+        "bull" => '•',
+        "times" => '×',
+        "Ccedil" => 'Ç',
+        "ccedil" => 'ç',
+        "Ouml" => 'Ö',
+        "ouml" => 'ö',
+        "Uuml" => 'Ü',
+        "uuml" => 'ü',
+        "Auml" => 'Ä',
+        "auml" => 'ä',
+        "szlig" => 'ß',
+        "eacute" => 'é',
+        "egrave" => 'è',
+        "aacute" => 'á',
+        "agrave" => 'à',
+        "iacute" => 'í',
+        "oacute" => 'ó',
+        "uacute" => 'ú',
+        "ntilde" => 'ñ',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Removes markdown syntax, keeping link text, image alt text and code.
+pub fn strip_markdown(text: &str) -> String {
+    let text = MD_CODE_FENCE_RE.replace_all(text, "");
+    let text = MD_IMAGE_RE.replace_all(&text, "$1");
+    let text = MD_LINK_RE.replace_all(&text, "$1");
+    let text = MD_RULE_RE.replace_all(&text, "");
+    let text = MD_HEADING_RE.replace_all(&text, "");
+    let text = MD_BLOCKQUOTE_RE.replace_all(&text, "");
+    let text = MD_LIST_RE.replace_all(&text, "");
+    let text = MD_BOLD_RE.replace_all(&text, "$1$2");
+    let text = MD_ITALIC_RE.replace_all(&text, "$1$2");
+    let text = MD_STRIKE_RE.replace_all(&text, "$1");
+    MD_INLINE_CODE_RE.replace_all(&text, "$1").to_string()
+}
+
+pub fn replace_urls(text: &str, replacement: &str) -> String {
+    URL_RE.replace_all(text, replacement).to_string()
+}
+
+pub fn mask_emails(text: &str, mask: &str) -> String {
+    EMAIL_RE.replace_all(text, mask).to_string()
+}
+
+pub fn normalize_unicode(text: &str, form: UnicodeForm) -> String {
+    match form {
+        UnicodeForm::None => text.to_string(),
+        UnicodeForm::Nfc => text.nfc().collect(),
+        UnicodeForm::Nfd => text.nfd().collect(),
+        UnicodeForm::Nfkc => text.nfkc().collect(),
+        UnicodeForm::Nfkd => text.nfkd().collect(),
+    }
+}
+
+pub fn strip_accents(text: &str) -> String {
+    // Decompose, drop the combining marks, then recompose what is left
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .nfc()
+        .collect()
+}
+
+pub fn fold_full_width(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\u{3000}' => ' ',
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+pub fn remove_control_characters(text: &str) -> String {
     text.chars()
-        .map(|c| c.to_lowercase().to_string())
-        .collect::<Vec<_>>()
-        .join("")
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+        .collect()
+}
+
+pub fn remove_zero_width_characters(text: &str) -> String {
+    text.chars()
+        .filter(|c| !matches!(
+            c,
+            '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}' | '\u{00AD}'
+        ))
+        .collect()
 }
 
 #[cfg(test)]
@@ -38,6 +208,76 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_remove_special_characters_keeps_non_latin_letters() {
+        assert_eq!(
+            remove_special_characters("Çok güzel! Größe: 42 — 日本語です。"),
+            "Çok güzel Größe 42  日本語です"
+        );
+    }
+
+    #[test]
+    fn test_normalize_unicode_forms() {
+        let decomposed = "e\u{0301}";
+        assert_eq!(normalize_unicode(decomposed, UnicodeForm::Nfc), "\u{00E9}");
+        assert_eq!(normalize_unicode("\u{00E9}", UnicodeForm::Nfd), decomposed);
+        assert_eq!(normalize_unicode("ﬁ", UnicodeForm::Nfkc), "fi");
+        assert_eq!(normalize_unicode("ﬁ", UnicodeForm::None), "ﬁ");
+    }
+
+    #[test]
+    fn test_strip_accents() {
+        assert_eq!(strip_accents("Şükrü café naïve"), "Sukru cafe naive");
+    }
+
+    #[test]
+    fn test_fold_full_width() {
+        assert_eq!(fold_full_width("ＡＢＣ１２３！\u{3000}ok"), "ABC123! ok");
+    }
+
+    #[test]
+    fn test_remove_control_and_zero_width_characters() {
+        assert_eq!(remove_control_characters("a\u{0007}b\nc"), "ab\nc");
+        assert_eq!(remove_zero_width_characters("zero\u{200B}width\u{FEFF}"), "zerowidth");
+    }
+
+    #[test]
+    fn test_strip_urls_and_mask_emails() {
+        assert_eq!(
+            replace_urls("see https://example.com/x?utm=1 or www.test.org", "[URL]"),
+            "see [URL] or [URL]"
+        );
+        assert_eq!(mask_emails("write to jane.doe+1@mail.co.uk", "[EMAIL]"), "write to [EMAIL]");
+    }
+
+    #[test]
+    fn test_strip_html_tags_keeps_text() {
+        assert_eq!(
+            strip_html_tags("<div><b>Great</b> product<br/>would<script>track()</script> buy<!-- x --></div>"),
+            " Great product would  buy  "
+        );
+        assert_eq!(strip_html_tags("<p>a</p><p>b</p>"), " a  b ");
+        assert_eq!(strip_html_tags("2 < 3 and 5 > 4"), "2 < 3 and 5 > 4");
+    }
+
+    #[test]
+    fn test_decode_html_entities() {
+        assert_eq!(
+            decode_html_entities("Fish &amp; chips &lt;3 &#8364;5 &#x1F600; &ouml;&nbsp;ok &bogus;"),
+            "Fish & chips <3 €5 😀 ö ok &bogus;"
+        );
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        let markdown = "# Title\n> quoted **bold** and _it_\n- item with [link](https://x.io)\n![alt](img.png) `code` ~~old~~\n```rust\nlet x = 1;\n```";
+        assert_eq!(
+            strip_markdown(markdown),
+            "Title\nquoted bold and it\nitem with link\nalt code old\n\nlet x = 1;\n"
+        );
+        assert_eq!(strip_markdown("snake_case_name stays"), "snake_case_name stays");
+    }
+
     #[test]
     fn test_normalize_whitespace() {
         assert_eq!(
@@ -1,19 +1,223 @@
-// Placeholder for pipeline implementation
-// This would contain the actual transformer pipeline logic
+// BERT-family encoder with the task heads used by the span and pair tasks.
+// Sequence classification for /predict still goes through the engine's
+// placeholder path.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use candle_core::{DType, Device, IndexOp, Tensor, D};
+use candle_nn::{linear, Linear, Module, VarBuilder};
+use candle_transformers::models::bert::{BertModel, Config as BertConfig};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::model::loader::get_model_info;
+use crate::preprocessing::tokenizer::EncodedInput;
 
 pub struct TransformerPipeline {
-    // Model components would go here
+    model: BertModel,
+    /// `classifier` on top of every token (`*ForTokenClassification`)
+    token_classifier: Option<Linear>,
+    /// `qa_outputs` producing start/end logits (`*ForQuestionAnswering`)
+    qa_outputs: Option<Linear>,
+    /// Head over the first token (`*ForSequenceClassification`)
+    sequence_head: Option<SequenceHead>,
+    labels: Vec<String>,
+    /// RoBERTa-style models have a single token type
+    use_type_ids: bool,
+    device: Device,
+}
+
+enum SequenceHead {
+    /// BERT: tanh pooler, then `classifier`
+    Pooled { pooler: Linear, classifier: Linear },
+    /// RoBERTa: `classifier.dense` (tanh), then `classifier.out_proj`
+    Projected { dense: Linear, out_proj: Linear },
+}
+
+impl SequenceHead {
+    fn forward(&self, first_token: &Tensor) -> Result<Tensor> {
+        let (hidden, output) = match self {
+            Self::Pooled { pooler, classifier } => (pooler, classifier),
+            Self::Projected { dense, out_proj } => (dense, out_proj),
+        };
+
+        Ok(output.forward(&hidden.forward(first_token)?.tanh()?)?)
+    }
 }
 
 impl TransformerPipeline {
-    pub fn new() -> Result<Self> {
-        Ok(Self {})
+    /// Loads `config.json` and `model.safetensors` from a model directory.
+    /// Heads are optional: a checkpoint only gets the ones it has weights for.
+    pub fn load(model_dir: &Path, device: &Device) -> Result<Self> {
+        let metadata = get_model_info(model_dir)?;
+        let hidden_size = metadata.hidden_size
+            .context("config.json has no hidden_size")?;
+        let labels = metadata.labels();
+        let model_type = metadata.model_type.clone().unwrap_or_else(|| "bert".to_string());
+
+        let config_content = std::fs::read_to_string(model_dir.join("config.json"))?;
+        let config: BertConfig = serde_json::from_str(&config_content)
+            .context("Unsupported model config (expected a BERT-family encoder)")?;
+
+        let weights = model_dir.join("model.safetensors");
+        if !weights.exists() {
+            anyhow::bail!("Model weights not found: {:?}", weights);
+        }
+
+        let vb = if model_type.contains("roberta") {
+            // RoBERTa counts positions from padding_idx + 1; candle's BERT
+            // embeddings count from 0, so shift the table to match
+            let mut tensors = candle_core::safetensors::load(&weights, device)?;
+            let offset = metadata.pad_token_id.unwrap_or(1) + 1;
+            for (name, tensor) in tensors.iter_mut() {
+                if name.ends_with("embeddings.position_embeddings.weight") {
+                    let rows = tensor.dim(0)?;
+                    *tensor = Tensor::cat(&[tensor.narrow(0, offset, rows - offset)?, tensor.narrow(0, 0, offset)?], 0)?;
+                }
+            }
+            VarBuilder::from_tensors(tensors, DType::F32, device)
+        } else {
+            // SAFETY: the weights file is not modified while it is mapped
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights], DType::F32, device)? }
+        };
+        let model = BertModel::load(vb.clone(), &config)?;
+
+        let token_classifier = if labels.is_empty() {
+            None
+        } else {
+            optional_linear(&vb, hidden_size, labels.len(), "classifier")?
+        };
+        let qa_outputs = optional_linear(&vb, hidden_size, 2, "qa_outputs")?;
+        let sequence_head = load_sequence_head(&vb, &model_type, hidden_size, labels.len())?;
+
+        tracing::info!(
+            "Pipeline loaded from {:?} ({} labels, token head: {}, qa head: {}, sequence head: {})",
+            model_dir,
+            labels.len(),
+            token_classifier.is_some(),
+            qa_outputs.is_some(),
+            sequence_head.is_some()
+        );
+
+        Ok(Self {
+            model,
+            token_classifier,
+            qa_outputs,
+            sequence_head,
+            labels,
+            use_type_ids: metadata.type_vocab_size.unwrap_or(2) > 1,
+            device: device.clone(),
+        })
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.labels
     }
 
-    pub async fn process(&self, input: &str) -> Result<Vec<f32>> {
-        // Actual pipeline processing would go here
-        Ok(vec![])
+    /// Last hidden state for one encoded sequence, shape `[seq_len, hidden]`.
+    pub fn hidden_states(&self, encoded: &EncodedInput) -> Result<Tensor> {
+        Ok(self.batch_hidden_states(&[encoded])?.squeeze(0)?)
     }
+
+    /// Last hidden states for sequences of equal length, shape
+    /// `[batch, seq_len, hidden]`.
+    fn batch_hidden_states(&self, batch: &[&EncodedInput]) -> Result<Tensor> {
+        let seq_len = batch.first().map_or(0, |encoded| encoded.len());
+        let ids: Vec<u32> = batch.iter().flat_map(|encoded| encoded.ids.iter().copied()).collect();
+        let type_ids: Vec<u32> = if self.use_type_ids {
+            batch.iter().flat_map(|encoded| encoded.type_ids.iter().copied()).collect()
+        } else {
+            vec![0; ids.len()]
+        };
+
+        let input_ids = Tensor::from_vec(ids, (batch.len(), seq_len), &self.device)?;
+        let type_ids = Tensor::from_vec(type_ids, (batch.len(), seq_len), &self.device)?;
+
+        Ok(self.model.forward(&input_ids, &type_ids)?)
+    }
+
+    /// Per-token label probabilities, shape `[seq_len][num_labels]`.
+    pub fn token_probabilities(&self, encoded: &EncodedInput) -> Result<Vec<Vec<f32>>> {
+        let head = self.token_classifier.as_ref()
+            .context("Model has no token-classification head")?;
+
+        let logits = head.forward(&self.hidden_states(encoded)?)?;
+        let probs = candle_nn::ops::softmax(&logits, D::Minus1)?;
+
+        Ok(probs.to_vec2::<f32>()?)
+    }
+
+    /// Start and end logits for every token of a question/context pair.
+    pub fn span_logits(&self, encoded: &EncodedInput) -> Result<(Vec<f32>, Vec<f32>)> {
+        let head = self.qa_outputs.as_ref()
+            .context("Model has no question-answering head")?;
+
+        let logits = head.forward(&self.hidden_states(encoded)?)?;
+        let start = logits.narrow(D::Minus1, 0, 1)?.squeeze(D::Minus1)?;
+        let end = logits.narrow(D::Minus1, 1, 1)?.squeeze(D::Minus1)?;
+
+        Ok((start.to_vec1::<f32>()?, end.to_vec1::<f32>()?))
+    }
+
+    /// Sequence-classification logits for each input, in input order.
+    /// candle's BERT takes no attention mask, so inputs are batched by
+    /// length rather than padded.
+    pub fn sequence_logits(&self, inputs: &[EncodedInput]) -> Result<Vec<Vec<f32>>> {
+        let head = self.sequence_head.as_ref()
+            .context("Model has no sequence-classification head")?;
+
+        let mut by_length: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (index, encoded) in inputs.iter().enumerate() {
+            by_length.entry(encoded.len()).or_default().push(index);
+        }
+
+        let mut logits = vec![Vec::new(); inputs.len()];
+        for indices in by_length.values() {
+            let batch: Vec<&EncodedInput> = indices.iter().map(|&i| &inputs[i]).collect();
+            let first_token = self.batch_hidden_states(&batch)?.i((.., 0))?;
+            let rows = head.forward(&first_token)?.to_vec2::<f32>()?;
+
+            for (&index, row) in indices.iter().zip(rows) {
+                logits[index] = row;
+            }
+        }
+
+        Ok(logits)
+    }
+}
+
+/// The linear layer at `prefix`, or `None` if the checkpoint has no weights
+/// there. Weights that are present but don't load (a shape mismatch, say)
+/// are an error rather than a missing head.
+fn optional_linear(vb: &VarBuilder, in_dim: usize, out_dim: usize, prefix: &str) -> candle_core::Result<Option<Linear>> {
+    let vb = vb.pp(prefix);
+    if !vb.contains_tensor("weight") {
+        return Ok(None);
+    }
+    linear(in_dim, out_dim, vb).map(Some)
+}
+
+fn load_sequence_head(
+    vb: &VarBuilder,
+    model_type: &str,
+    hidden_size: usize,
+    num_labels: usize,
+) -> candle_core::Result<Option<SequenceHead>> {
+    if num_labels == 0 {
+        return Ok(None);
+    }
+
+    // RoBERTa-style head on the <s> token
+    if let Some(dense) = optional_linear(vb, hidden_size, hidden_size, "classifier.dense")? {
+        let out_proj = linear(hidden_size, num_labels, vb.pp("classifier.out_proj"))?;
+        return Ok(Some(SequenceHead::Projected { dense, out_proj }));
+    }
+
+    // BERT-style pooler, at the root or under the model type
+    let pooler = match optional_linear(vb, hidden_size, hidden_size, "pooler.dense")? {
+        Some(pooler) => Some(pooler),
+        None => optional_linear(vb, hidden_size, hidden_size, &format!("{}.pooler.dense", model_type))?,
+    };
+    let classifier = optional_linear(vb, hidden_size, num_labels, "classifier")?;
+
+    Ok(pooler.zip(classifier).map(|(pooler, classifier)| SequenceHead::Pooled { pooler, classifier }))
 }
@@ -3,27 +3,19 @@ use crate::config::AppConfig;
 
 pub mod tokenizer;
 pub mod normalizer;
+pub mod truncation;
+pub mod chain;
+pub mod redaction;
+pub mod language;
+pub mod chat_template;
 
-pub fn preprocess_text(input: &str, config: &AppConfig) -> Result<String> {
-    let mut text = input.to_string();
+/// Runs the preprocessing chain configured for `model_name`.
+pub fn preprocess_text(input: &str, model_name: &str, config: &AppConfig) -> Result<String> {
+    // Hard cap on raw characters; token-level truncation happens later
+    // against the model's max_length
+    let text = chain::truncate_chars(input, config.preprocessing.max_input_length);
 
-    // Normalize
-    if config.preprocessing.lowercase {
-        text = text.to_lowercase();
-    }
+    let steps = chain::resolve_chain(config, model_name);
 
-    if config.preprocessing.remove_special_chars {
-        text = normalizer::remove_special_characters(&text);
-    }
-
-    // Truncate if too long
-    if text.len() > config.preprocessing.max_input_length {
-        text.truncate(config.preprocessing.max_input_length);
-    }
-
-    // Additional normalization
-    text = normalizer::normalize_whitespace(&text);
-    text = normalizer::remove_extra_spaces(&text);
-
-    Ok(text)
+    Ok(chain::run_chain(text, &steps))
 }
@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+
+use crate::preprocessing::tokenizer::{CharIndex, EncodedInput};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Answer {
+    pub answer: String,
+    /// P(start) * P(end) within the window the span came from
+    pub score: f32,
+    /// Char offsets into the context
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Best answer spans in one question/context window. Only context tokens
+/// (sequence 1) can start or end an answer; `max_answer_len` is in tokens.
+/// `chars` indexes `context` and is shared by all its windows.
+pub fn best_spans(
+    context: &str,
+    chars: &CharIndex,
+    encoded: &EncodedInput,
+    start_logits: &[f32],
+    end_logits: &[f32],
+    max_answer_len: usize,
+    top_k: usize,
+) -> Vec<Answer> {
+    let tokens: Vec<usize> = (0..encoded.len().min(start_logits.len()).min(end_logits.len()))
+        .filter(|&i| encoded.sequence_ids[i] == Some(1))
+        .filter(|&i| encoded.special_tokens_mask.get(i) != Some(&1))
+        .collect();
+
+    let start_probs = softmax(tokens.iter().map(|&i| start_logits[i]));
+    let end_probs = softmax(tokens.iter().map(|&i| end_logits[i]));
+
+    let mut candidates = Vec::new();
+    for (s, &start_token) in tokens.iter().enumerate() {
+        for (e, &end_token) in tokens.iter().enumerate().skip(s) {
+            if end_token - start_token >= max_answer_len {
+                break;
+            }
+            candidates.push((start_probs[s] * end_probs[e], start_token, end_token));
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    candidates
+        .into_iter()
+        .take(top_k)
+        .map(|(score, start_token, end_token)| {
+            let start = encoded.offsets[start_token].0;
+            let end = encoded.offsets[end_token].1;
+            let (char_start, char_end) = chars.char_range((start, end));
+
+            Answer {
+                answer: context[start..end].to_string(),
+                score,
+                start: char_start,
+                end: char_end,
+            }
+        })
+        .collect()
+}
+
+/// Merges answers from overlapping windows: the same span found in several
+/// windows keeps its best score.
+pub fn merge_answers(answers: Vec<Answer>, top_k: usize) -> Vec<Answer> {
+    let mut merged: Vec<Answer> = Vec::with_capacity(answers.len());
+    for answer in answers {
+        match merged.iter_mut().find(|a| a.start == answer.start && a.end == answer.end) {
+            Some(existing) => existing.score = existing.score.max(answer.score),
+            None => merged.push(answer),
+        }
+    }
+
+    merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+    merged.truncate(top_k);
+    merged
+}
+
+fn softmax(logits: impl Iterator<Item = f32> + Clone) -> Vec<f32> {
+    let max = logits.clone().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = logits.map(|logit| (logit - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.into_iter().map(|exp| exp / sum).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTEXT: &str = "Açme ships from İzmir daily";
+
+    // [CLS] where ? [SEP] Aç ##me ships from İzmir daily [SEP]
+    fn encoded() -> EncodedInput {
+        EncodedInput {
+            ids: vec![101, 1, 2, 102, 3, 4, 5, 6, 7, 8, 102],
+            tokens: ["[CLS]", "where", "?", "[SEP]", "Aç", "##me", "ships", "from", "İzmir", "daily", "[SEP]"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            offsets: vec![(0, 0), (0, 5), (5, 6), (0, 0), (0, 3), (3, 5), (6, 11), (12, 16), (17, 23), (24, 29), (0, 0)],
+            sequence_ids: vec![None, Some(0), Some(0), None, Some(1), Some(1), Some(1), Some(1), Some(1), Some(1), None],
+            word_ids: vec![None, Some(0), Some(1), None, Some(0), Some(0), Some(1), Some(2), Some(3), Some(4), None],
+            attention_mask: vec![1; 11],
+            type_ids: vec![0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1],
+            special_tokens_mask: vec![1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1],
+            overflowing: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_best_span_uses_char_offsets() {
+        let mut start = vec![0.0; 11];
+        let mut end = vec![0.0; 11];
+        // The question tokens score highest but can never be an answer
+        start[1] = 20.0;
+        end[2] = 20.0;
+        start[8] = 10.0;
+        end[8] = 10.0;
+
+        let answers = best_spans(CONTEXT, &CharIndex::new(CONTEXT), &encoded(), &start, &end, 15, 1);
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].answer, "İzmir");
+        assert_eq!((answers[0].start, answers[0].end), (16, 21));
+        assert!(answers[0].score > 0.99);
+    }
+
+    #[test]
+    fn test_answers_come_from_the_echo_redacted_context() {
+        use crate::preprocessing::redaction::{redact_for_all, RedactionConfig, RedactionPolicy, RedactionTarget};
+
+        let config = RedactionConfig {
+            enabled: true,
+            model_input: RedactionPolicy::Keep,
+            response_echo: RedactionPolicy::Mask,
+            ..Default::default()
+        };
+        let targets = [RedactionTarget::ModelInput, RedactionTarget::ResponseEcho];
+        let context = redact_for_all("Write to ali@example.com today", &config, &targets);
+        assert_eq!(context, "Write to [EMAIL] today");
+
+        // [CLS] who ? [SEP] Write to [EMAIL] today [SEP]
+        let encoded = EncodedInput {
+            ids: vec![101, 1, 2, 102, 3, 4, 5, 6, 102],
+            tokens: ["[CLS]", "who", "?", "[SEP]", "Write", "to", "[EMAIL]", "today", "[SEP]"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            offsets: vec![(0, 0), (0, 3), (3, 4), (0, 0), (0, 5), (6, 8), (9, 16), (17, 22), (0, 0)],
+            sequence_ids: vec![None, Some(0), Some(0), None, Some(1), Some(1), Some(1), Some(1), None],
+            word_ids: vec![None, Some(0), Some(1), None, Some(0), Some(1), Some(2), Some(3), None],
+            attention_mask: vec![1; 9],
+            type_ids: vec![0, 0, 0, 0, 1, 1, 1, 1, 1],
+            special_tokens_mask: vec![1, 0, 0, 1, 0, 0, 0, 0, 1],
+            overflowing: Vec::new(),
+        };
+        let mut start = vec![0.0; 9];
+        let mut end = vec![0.0; 9];
+        start[6] = 10.0;
+        end[6] = 10.0;
+
+        let answers = best_spans(&context, &CharIndex::new(&context), &encoded, &start, &end, 15, 1);
+        assert_eq!(answers[0].answer, "[EMAIL]");
+    }
+
+    #[test]
+    fn test_max_answer_len_limits_spans() {
+        let mut start = vec![0.0; 11];
+        let mut end = vec![0.0; 11];
+        start[4] = 10.0;
+        end[9] = 10.0;
+
+        let long = best_spans(CONTEXT, &CharIndex::new(CONTEXT), &encoded(), &start, &end, 15, 1);
+        assert_eq!(long[0].answer, CONTEXT);
+
+        let short = best_spans(CONTEXT, &CharIndex::new(CONTEXT), &encoded(), &start, &end, 2, 3);
+        assert!(short.iter().all(|a| a.answer != CONTEXT));
+        assert_eq!(short.len(), 3);
+    }
+
+    #[test]
+    fn test_merge_answers_dedupes_overlapping_windows() {
+        let answer = |score: f32, start: usize| Answer { answer: "x".into(), score, start, end: start + 1 };
+        let merged = merge_answers(vec![answer(0.2, 0), answer(0.5, 3), answer(0.7, 0)], 5);
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!((merged[0].start, merged[0].score), (0, 0.7));
+        assert_eq!((merged[1].start, merged[1].score), (3, 0.5));
+    }
+}
@@ -0,0 +1,707 @@
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::auth::{current_caller, Caller};
+use super::{error::ApiError, AppState};
+use crate::config::{QuotaConfig, RateLimitConfig, RateLimits};
+use crate::preprocessing::tokenizer::CustomTokenizer;
+
+/// Request bodies are buffered to count tokens; same limit as `Json`.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Body fields holding text that counts against token limits.
+const INPUT_FIELDS: &[&str] = &["text", "texts", "text_pair", "question", "context", "candidate_labels", "messages"];
+
+/// Refills continuously at `per_minute` and holds at most a minute's worth.
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u64, now: Instant) -> Self {
+        Self {
+            capacity: per_minute as f64,
+            available: per_minute as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Time until `cost` is available. Costs above the capacity only need a
+    /// full bucket and leave it in debt, so large requests are slowed rather
+    /// than refused forever.
+    fn wait_for(&self, cost: f64) -> Duration {
+        let missing = cost.min(self.capacity) - self.available;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing * 60.0 / self.capacity)
+        }
+    }
+
+    fn take(&mut self, cost: f64) {
+        self.available -= cost;
+    }
+
+    fn remaining(&self) -> u64 {
+        self.available.max(0.0) as u64
+    }
+
+    /// Time until the bucket is full again.
+    fn reset_after(&self) -> Duration {
+        Duration::from_secs_f64((self.capacity - self.available).max(0.0) * 60.0 / self.capacity)
+    }
+}
+
+/// The request and token buckets of one key or IP.
+#[derive(Debug, Clone)]
+struct ClientBuckets {
+    requests: Option<TokenBucket>,
+    tokens: Option<TokenBucket>,
+}
+
+impl ClientBuckets {
+    fn new(limits: &RateLimits, now: Instant) -> Self {
+        Self {
+            requests: limits.requests_per_minute.map(|limit| TokenBucket::new(limit, now)),
+            tokens: limits.tokens_per_minute.map(|limit| TokenBucket::new(limit, now)),
+        }
+    }
+
+    /// Time until both buckets can pay for a request of `tokens`.
+    fn wait_for(&self, tokens: u64) -> Duration {
+        let request = self.requests.as_ref().map_or(Duration::ZERO, |bucket| bucket.wait_for(1.0));
+        let tokens = self.tokens.as_ref().map_or(Duration::ZERO, |bucket| bucket.wait_for(tokens as f64));
+        request.max(tokens)
+    }
+
+    /// Pays for a request; check `wait_for` first.
+    fn take(&mut self, tokens: u64) {
+        if let Some(bucket) = &mut self.requests {
+            bucket.take(1.0);
+        }
+        if let Some(bucket) = &mut self.tokens {
+            bucket.take(tokens as f64);
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        self.requests.iter_mut().chain(self.tokens.iter_mut()).for_each(|bucket| bucket.refill(now));
+    }
+
+    /// Full buckets are indistinguishable from new ones.
+    fn is_full(&self) -> bool {
+        self.requests.iter().chain(self.tokens.iter()).all(|bucket| bucket.available >= bucket.capacity)
+    }
+}
+
+/// What the `X-RateLimit-*` headers report: the request bucket of the
+/// client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimitStatus {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_secs: u64,
+}
+
+impl LimitStatus {
+    fn of(bucket: &TokenBucket) -> Self {
+        Self {
+            limit: bucket.capacity as u64,
+            remaining: bucket.remaining(),
+            reset_secs: bucket.reset_after().as_secs_f64().ceil() as u64,
+        }
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        headers.insert("x-ratelimit-limit", HeaderValue::from(self.limit));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from(self.remaining));
+        headers.insert("x-ratelimit-reset", HeaderValue::from(self.reset_secs));
+    }
+}
+
+/// A request turned away by `RateLimiter::check`. The 429 still carries
+/// the `X-RateLimit-*` headers, which clients need most right then.
+#[derive(Debug)]
+pub struct Rejection {
+    pub error: ApiError,
+    pub status: Option<LimitStatus>,
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let mut response = self.error.into_response();
+        if let Some(status) = self.status {
+            status.apply(response.headers_mut());
+        }
+        response
+    }
+}
+
+/// Requests and tokens counted for one key in the current day and month (UTC).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub day: Option<NaiveDate>,
+    pub daily_requests: u64,
+    pub daily_tokens: u64,
+    /// First day of the month being counted
+    pub month: Option<NaiveDate>,
+    pub monthly_requests: u64,
+    pub monthly_tokens: u64,
+}
+
+impl Usage {
+    /// Starts new counters when the day or month has changed.
+    fn roll(&mut self, today: NaiveDate) {
+        if self.day != Some(today) {
+            self.day = Some(today);
+            self.daily_requests = 0;
+            self.daily_tokens = 0;
+        }
+        let month = today.with_day(1);
+        if self.month != month {
+            self.month = month;
+            self.monthly_requests = 0;
+            self.monthly_tokens = 0;
+        }
+    }
+
+    /// The first quota this request would exceed, and seconds until it resets.
+    fn exceeded(&self, quotas: &QuotaConfig, tokens: u64, now: DateTime<Utc>) -> Option<(&'static str, u64)> {
+        let over = |used: u64, cost: u64, quota: Option<u64>| quota.is_some_and(|quota| used + cost > quota);
+
+        if over(self.daily_requests, 1, quotas.daily_requests) || over(self.daily_tokens, tokens, quotas.daily_tokens) {
+            return Some(("daily", seconds_until(next_day(now), now)));
+        }
+        if over(self.monthly_requests, 1, quotas.monthly_requests)
+            || over(self.monthly_tokens, tokens, quotas.monthly_tokens)
+        {
+            return Some(("monthly", seconds_until(next_month(now), now)));
+        }
+        None
+    }
+}
+
+fn next_day(now: DateTime<Utc>) -> NaiveDate {
+    now.date_naive() + ChronoDuration::days(1)
+}
+
+fn next_month(now: DateTime<Utc>) -> NaiveDate {
+    let (year, month) = if now.month() == 12 { (now.year() + 1, 1) } else { (now.year(), now.month() + 1) };
+    NaiveDate::from_ymd_opt(year, month, 1).unwrap()
+}
+
+fn seconds_until(date: NaiveDate, now: DateTime<Utc>) -> u64 {
+    let start = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    (start - now).num_seconds().max(1) as u64
+}
+
+/// Per-key usage, kept in memory and flushed to `rate_limit.usage_file` so
+/// quotas survive restarts.
+pub struct UsageStore {
+    path: PathBuf,
+    usage: Mutex<HashMap<String, Usage>>,
+    dirty: AtomicBool,
+}
+
+impl UsageStore {
+    pub fn load(path: &Path) -> Result<Self> {
+        let usage = if path.exists() {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read usage file {:?}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Usage file {:?} is corrupt", path))?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            usage: Mutex::new(usage),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Writes the counters if they changed since the last flush.
+    pub fn flush(&self) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let content = serde_json::to_string_pretty(&*self.usage.lock().unwrap())?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write then rename so a crash never leaves a half-written file
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, content)?;
+        std::fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, subject: &str, now: DateTime<Utc>) -> Usage {
+        let mut usage = self.usage.lock().unwrap().get(subject).cloned().unwrap_or_default();
+        usage.roll(now.date_naive());
+        usage
+    }
+
+    /// Counts the request unless it would exceed a quota.
+    fn charge(&self, subject: &str, quotas: &QuotaConfig, tokens: u64, now: DateTime<Utc>) -> Result<(), ApiError> {
+        let mut all = self.usage.lock().unwrap();
+        let usage = all.entry(subject.to_string()).or_default();
+        usage.roll(now.date_naive());
+
+        if let Some((period, retry_after_secs)) = usage.exceeded(quotas, tokens, now) {
+            return Err(ApiError::QuotaExceeded { period, retry_after_secs });
+        }
+
+        usage.daily_requests += 1;
+        usage.daily_tokens += tokens;
+        usage.monthly_requests += 1;
+        usage.monthly_tokens += tokens;
+        self.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Token-bucket limits per API key and per client IP, plus per-key quotas.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    /// One lock over all buckets, so a request's key and IP buckets are
+    /// checked and charged together
+    buckets: Mutex<HashMap<String, ClientBuckets>>,
+    pub usage: UsageStore,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Result<Self> {
+        Ok(Self {
+            config: config.clone(),
+            buckets: Mutex::new(HashMap::new()),
+            usage: UsageStore::load(&config.usage_file)?,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Drops buckets that have refilled completely; the next request from
+    /// that client starts a fresh, equally full one. Returns how many went.
+    pub fn evict_full_buckets(&self) -> usize {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+        buckets.retain(|_, client| {
+            client.refill(now);
+            !client.is_full()
+        });
+        before - buckets.len()
+    }
+
+    /// Whether any limit or quota is counted in tokens, so bodies need
+    /// tokenizing.
+    pub fn counts_tokens(&self, caller: Option<&Caller>) -> bool {
+        let key_tokens = caller.is_some_and(|caller| {
+            let quotas = self.quotas(caller);
+            self.key_limits(Some(caller)).tokens_per_minute.is_some()
+                || quotas.daily_tokens.is_some()
+                || quotas.monthly_tokens.is_some()
+        });
+        key_tokens || self.config.per_ip.tokens_per_minute.is_some()
+    }
+
+    /// Quotas belong to keys; requests without one are only limited per IP.
+    pub fn quotas<'a>(&'a self, caller: &'a Caller) -> &'a QuotaConfig {
+        caller.quotas.as_ref().unwrap_or(&self.config.quotas)
+    }
+
+    fn key_limits<'a>(&'a self, caller: Option<&'a Caller>) -> &'a RateLimits {
+        caller.and_then(|caller| caller.rate_limits.as_ref()).unwrap_or(&self.config.per_key)
+    }
+
+    /// Admits a request costing `tokens`, charging the key's buckets, the
+    /// IP's buckets and the key's quotas. Everything is checked before
+    /// anything is charged, so a rejected request costs nothing. Without a
+    /// key there are no quotas: one shared subject would let a single client
+    /// use up everyone's allowance.
+    pub fn check(&self, caller: Option<&Caller>, ip: Option<&str>, tokens: u64) -> Result<Option<LimitStatus>, Rejection> {
+        let now = Instant::now();
+        let mut subjects = Vec::new();
+        if let Some(caller) = caller {
+            subjects.push((format!("key:{}", caller.id), self.key_limits(Some(caller))));
+        }
+        if let Some(ip) = ip {
+            subjects.push((format!("ip:{}", ip), &self.config.per_ip));
+        }
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut wait = Duration::ZERO;
+        for (subject, limits) in &subjects {
+            let client = buckets
+                .entry(subject.clone())
+                .or_insert_with(|| ClientBuckets::new(limits, now));
+            client.refill(now);
+            wait = wait.max(client.wait_for(tokens));
+        }
+        // The first request bucket, the key's if there is one, is reported
+        let status = |buckets: &HashMap<String, ClientBuckets>| {
+            subjects
+                .iter()
+                .find_map(|(subject, _)| buckets.get(subject)?.requests.as_ref().map(LimitStatus::of))
+        };
+        if !wait.is_zero() {
+            return Err(Rejection {
+                error: ApiError::RateLimited {
+                    retry_after_secs: wait.as_secs_f64().ceil().max(1.0) as u64,
+                },
+                status: status(&buckets),
+            });
+        }
+
+        // Checks the quota and counts the request in one step; the bucket
+        // lock is still held, so the buckets can't change meanwhile
+        if let Some(caller) = caller {
+            if let Err(error) = self.usage.charge(&caller.id, self.quotas(caller), tokens, Utc::now()) {
+                return Err(Rejection { error, status: status(&buckets) });
+            }
+        }
+
+        for (subject, _) in &subjects {
+            if let Some(client) = buckets.get_mut(subject) {
+                client.take(tokens);
+            }
+        }
+
+        Ok(status(&buckets))
+    }
+}
+
+/// Flushes usage counters every `interval` in the background, and drops
+/// the buckets of clients that have gone quiet so one entry per IP doesn't
+/// pile up forever.
+pub fn spawn_usage_flush(limiter: Arc<RateLimiter>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = limiter.usage.flush() {
+                tracing::warn!("Failed to persist usage counters: {:#}", e);
+            }
+            let evicted = limiter.evict_full_buckets();
+            if evicted > 0 {
+                tracing::debug!("Evicted {} idle rate limit buckets", evicted);
+            }
+        }
+    });
+}
+
+/// Applies rate limits and quotas before the handler runs. Runs inside
+/// `authorize` so the caller's key is known.
+pub async fn rate_limit_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let limiter = &state.rate_limiter;
+    if !limiter.enabled() {
+        return next.run(req).await;
+    }
+
+    let caller = current_caller();
+    let ip = client_ip(&req, limiter.config.trust_forwarded_for);
+
+    let (req, tokens) = if limiter.counts_tokens(caller.as_ref()) {
+        match count_input_tokens(&state, req).await {
+            Ok(counted) => counted,
+            Err(e) => return e.into_response(),
+        }
+    } else {
+        (req, 0)
+    };
+
+    let status = match limiter.check(caller.as_ref(), ip.as_deref(), tokens) {
+        Ok(status) => status,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let mut response = next.run(req).await;
+    if let Some(status) = status {
+        status.apply(response.headers_mut());
+    }
+    response
+}
+
+fn client_ip(req: &Request<Body>, trust_forwarded_for: bool) -> Option<String> {
+    let forwarded = trust_forwarded_for
+        .then(|| req.headers().get("x-forwarded-for"))
+        .flatten()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(|ip| ip.trim().to_string());
+
+    forwarded.or_else(|| {
+        req.extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string())
+    })
+}
+
+/// Buffers the body and counts the tokens of its input fields. Bodies that
+/// aren't JSON count as zero; the handler rejects them.
+///
+/// The count is an estimate: limits run before a model is loaded or routed,
+/// and counting must not load one. A requested model's tokenizer is used
+/// once it is loaded; otherwise, and for requests language routing sends
+/// elsewhere, the active model's tokenizer counts. Encoding runs on the
+/// blocking pool, like the engine's other CPU work.
+async fn count_input_tokens(state: &AppState, req: Request<Body>) -> Result<(Request<Body>, u64), ApiError> {
+    let (parts, body) = req.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| ApiError::InvalidParameters("Request body is too large".to_string()))?;
+
+    let tokens = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(value) => {
+            let manager = &state.model_manager;
+            let requested = value
+                .get("model")
+                .and_then(|model| model.as_str())
+                .and_then(|model| manager.loaded_tokenizer(model));
+            let tokenizer = match requested {
+                Some(tokenizer) => tokenizer,
+                None => {
+                    let active = manager.get_active_model().await;
+                    manager.get_tokenizer(active.as_deref().unwrap_or_default())
+                }
+            };
+
+            tokio::task::spawn_blocking(move || count_tokens(&tokenizer, &value))
+                .await
+                .map_err(anyhow::Error::from)?
+        }
+        Err(_) => 0,
+    };
+
+    Ok((Request::from_parts(parts, Body::from(bytes)), tokens))
+}
+
+/// Tokens in the input fields of a JSON body.
+fn count_tokens(tokenizer: &CustomTokenizer, value: &serde_json::Value) -> u64 {
+    let mut texts = Vec::new();
+    for field in INPUT_FIELDS {
+        if let Some(field) = value.get(field) {
+            collect_strings(field, &mut texts);
+        }
+    }
+    texts
+        .iter()
+        .map(|text| tokenizer.encode_untruncated(text, None).map_or(0, |encoded| encoded.len() as u64))
+        .sum()
+}
+
+fn collect_strings<'a>(value: &'a serde_json::Value, out: &mut Vec<&'a str>) {
+    match value {
+        serde_json::Value::String(text) => out.push(text),
+        serde_json::Value::Array(items) => items.iter().for_each(|item| collect_strings(item, out)),
+        serde_json::Value::Object(fields) => fields.values().for_each(|item| collect_strings(item, out)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiKeyConfig;
+
+    fn try_take(buckets: &mut ClientBuckets, tokens: u64) -> Result<(), Duration> {
+        let wait = buckets.wait_for(tokens);
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        buckets.take(tokens);
+        Ok(())
+    }
+
+    fn key(id: &str) -> Caller {
+        Arc::new(ApiKeyConfig {
+            id: id.to_string(),
+            key_hash: String::new(),
+            scopes: Vec::new(),
+            models: Vec::new(),
+            priority: None,
+            rate_limits: None,
+            quotas: None,
+        })
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let start = Instant::now();
+        let limits = RateLimits { requests_per_minute: Some(2), tokens_per_minute: Some(100) };
+        let mut buckets = ClientBuckets::new(&limits, start);
+
+        assert!(try_take(&mut buckets, 60).is_ok());
+        // Not enough tokens left, and nothing is taken from the request bucket
+        let wait = try_take(&mut buckets, 60).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(12));
+        assert_eq!(buckets.requests.as_ref().unwrap().remaining(), 1);
+
+        let later = start + Duration::from_secs(12);
+        buckets.refill(later);
+        assert!(try_take(&mut buckets, 60).is_ok());
+        assert_eq!(buckets.requests.as_ref().unwrap().remaining(), 0);
+    }
+
+    #[test]
+    fn test_oversized_cost_needs_full_bucket() {
+        let start = Instant::now();
+        let limits = RateLimits { requests_per_minute: None, tokens_per_minute: Some(100) };
+        let mut buckets = ClientBuckets::new(&limits, start);
+
+        assert!(try_take(&mut buckets, 250).is_ok());
+        buckets.refill(start + Duration::from_secs(60));
+        assert!(try_take(&mut buckets, 1).is_err());
+    }
+
+    #[test]
+    fn test_quotas_reset_with_the_period() {
+        let quotas = QuotaConfig { daily_tokens: Some(100), ..QuotaConfig::default() };
+        let now = "2026-01-31T23:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let mut usage = Usage::default();
+        usage.roll(now.date_naive());
+        usage.daily_tokens = 90;
+
+        assert_eq!(usage.exceeded(&quotas, 20, now), Some(("daily", 3600)));
+        assert_eq!(usage.exceeded(&quotas, 10, now), None);
+
+        let tomorrow = now + ChronoDuration::hours(2);
+        usage.roll(tomorrow.date_naive());
+        assert_eq!(usage.daily_tokens, 0);
+        assert_eq!(usage.month, NaiveDate::from_ymd_opt(2026, 2, 1));
+    }
+
+    #[test]
+    fn test_full_ip_bucket_leaves_the_key_bucket_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RateLimitConfig {
+            enabled: true,
+            per_key: RateLimits { requests_per_minute: Some(10), tokens_per_minute: None },
+            per_ip: RateLimits { requests_per_minute: Some(1), tokens_per_minute: None },
+            usage_file: dir.path().join("usage.json"),
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::new(&config).unwrap();
+        let caller = key("ci");
+
+        assert!(limiter.check(Some(&caller), Some("10.0.0.1"), 0).is_ok());
+        let rejected = limiter.check(Some(&caller), Some("10.0.0.1"), 0).unwrap_err();
+        assert_eq!(rejected.error.code(), "rate_limited");
+
+        // Only the admitted requests were charged to the key
+        let status = limiter.check(Some(&caller), Some("10.0.0.2"), 0).unwrap().unwrap();
+        assert_eq!(status.remaining, 8);
+        assert_eq!(limiter.usage.get("ci", Utc::now()).daily_requests, 2);
+    }
+
+    #[test]
+    fn test_full_buckets_are_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RateLimitConfig {
+            enabled: true,
+            per_ip: RateLimits { requests_per_minute: Some(60_000), tokens_per_minute: None },
+            usage_file: dir.path().join("usage.json"),
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::new(&config).unwrap();
+
+        limiter.check(None, Some("10.0.0.1"), 0).unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+
+        // A thousand requests a second refill one request in a millisecond
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(limiter.evict_full_buckets(), 1);
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_requests_without_a_key_have_no_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RateLimitConfig {
+            enabled: true,
+            quotas: QuotaConfig { daily_requests: Some(1), ..QuotaConfig::default() },
+            usage_file: dir.path().join("usage.json"),
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::new(&config).unwrap();
+
+        for ip in ["10.0.0.1", "10.0.0.2", "10.0.0.1"] {
+            assert!(limiter.check(None, Some(ip), 0).is_ok());
+        }
+        assert!(limiter.check(Some(&key("ci")), None, 0).is_ok());
+        assert!(limiter.check(Some(&key("ci")), None, 0).is_err());
+    }
+
+    #[test]
+    fn test_quota_rejection_leaves_buckets_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RateLimitConfig {
+            enabled: true,
+            per_key: RateLimits { requests_per_minute: Some(10), tokens_per_minute: None },
+            quotas: QuotaConfig { daily_tokens: Some(100), ..QuotaConfig::default() },
+            usage_file: dir.path().join("usage.json"),
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::new(&config).unwrap();
+        let caller = key("ci");
+
+        let rejected = limiter.check(Some(&caller), None, 500).unwrap_err();
+        assert_eq!(rejected.error.code(), "quota_exceeded");
+        assert_eq!(rejected.status.unwrap().remaining, 10);
+        let status = limiter.check(Some(&caller), None, 50).unwrap().unwrap();
+        assert_eq!(status.remaining, 9);
+    }
+
+    #[test]
+    fn test_rejections_carry_limit_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RateLimitConfig {
+            enabled: true,
+            per_key: RateLimits { requests_per_minute: Some(1), tokens_per_minute: None },
+            usage_file: dir.path().join("usage.json"),
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::new(&config).unwrap();
+        let caller = key("ci");
+
+        limiter.check(Some(&caller), None, 0).unwrap();
+        let response = limiter.check(Some(&caller), None, 0).unwrap_err().into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["x-ratelimit-limit"], "1");
+        assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+        assert!(response.headers().contains_key("x-ratelimit-reset"));
+        assert!(response.headers().contains_key(axum::http::header::RETRY_AFTER));
+    }
+}
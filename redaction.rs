@@ -0,0 +1,407 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+lazy_static! {
+    pub(crate) static ref EMAIL_RE: Regex = Regex::new(r"[\w.+-]+@[\w-]+(?:\.[\w-]+)+").unwrap();
+    static ref IBAN_RE: Regex = Regex::new(r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]){11,30}\b").unwrap();
+    static ref CARD_RE: Regex = Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap();
+    static ref TC_KIMLIK_RE: Regex = Regex::new(r"\b[1-9]\d{10}\b").unwrap();
+    static ref SSN_RE: Regex = Regex::new(r"\b\d{3}-\d{2}-\d{4}\b").unwrap();
+    static ref PHONE_RE: Regex =
+        Regex::new(r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{1,4}\)[ .-]?)?\d{2,4}(?:[ .-]?\d{2,4}){2,4}").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiKind {
+    Email,
+    Iban,
+    CreditCard,
+    NationalId,
+    Phone,
+}
+
+impl PiiKind {
+    pub const ALL: [PiiKind; 5] = [
+        PiiKind::Email,
+        PiiKind::Iban,
+        PiiKind::CreditCard,
+        PiiKind::NationalId,
+        PiiKind::Phone,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            PiiKind::Email => "EMAIL",
+            PiiKind::Iban => "IBAN",
+            PiiKind::CreditCard => "CREDIT_CARD",
+            PiiKind::NationalId => "NATIONAL_ID",
+            PiiKind::Phone => "PHONE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionPolicy {
+    /// Leave detected values as they are
+    #[default]
+    Keep,
+    /// Replace with a `[KIND]` placeholder
+    Mask,
+    /// Replace with `[KIND:<salted sha256 prefix>]`, stable across requests
+    Hash,
+    /// Remove the value entirely
+    Drop,
+}
+
+impl RedactionPolicy {
+    /// How little of a value survives: Keep < Hash < Mask < Drop.
+    fn strictness(self) -> u8 {
+        match self {
+            RedactionPolicy::Keep => 0,
+            RedactionPolicy::Hash => 1,
+            RedactionPolicy::Mask => 2,
+            RedactionPolicy::Drop => 3,
+        }
+    }
+}
+
+/// Where a piece of text is headed; each target has its own policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactionTarget {
+    ModelInput,
+    ResponseEcho,
+    Logs,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_detectors")]
+    pub detectors: Vec<PiiKind>,
+    #[serde(default)]
+    pub model_input: RedactionPolicy,
+    #[serde(default)]
+    pub response_echo: RedactionPolicy,
+    #[serde(default)]
+    pub logs: RedactionPolicy,
+    #[serde(default)]
+    pub hash_salt: String,
+}
+
+fn default_detectors() -> Vec<PiiKind> {
+    PiiKind::ALL.to_vec()
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            detectors: default_detectors(),
+            model_input: RedactionPolicy::Keep,
+            response_echo: RedactionPolicy::Keep,
+            logs: RedactionPolicy::Keep,
+            hash_salt: String::new(),
+        }
+    }
+}
+
+impl RedactionConfig {
+    pub fn policy(&self, target: RedactionTarget) -> RedactionPolicy {
+        if !self.enabled {
+            return RedactionPolicy::Keep;
+        }
+
+        match target {
+            RedactionTarget::ModelInput => self.model_input,
+            RedactionTarget::ResponseEcho => self.response_echo,
+            RedactionTarget::Logs => self.logs,
+        }
+    }
+
+    /// The strictest policy among `targets`, for text headed to all of them.
+    pub fn strictest_policy(&self, targets: &[RedactionTarget]) -> RedactionPolicy {
+        targets
+            .iter()
+            .map(|target| self.policy(*target))
+            .max_by_key(|policy| policy.strictness())
+            .unwrap_or(RedactionPolicy::Keep)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PiiMatch {
+    pub kind: PiiKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Applies the policy configured for `target` to `text`.
+pub fn redact_for<'a>(text: &'a str, config: &RedactionConfig, target: RedactionTarget) -> Cow<'a, str> {
+    redact_with(text, config, config.policy(target))
+}
+
+/// Applies the strictest policy among `targets`, for text that is both fed
+/// to the model and echoed back, such as NER input and QA context.
+pub fn redact_for_all<'a>(text: &'a str, config: &RedactionConfig, targets: &[RedactionTarget]) -> Cow<'a, str> {
+    redact_with(text, config, config.strictest_policy(targets))
+}
+
+/// Config for text logged or returned from places that don't carry the
+/// app config: the access log, request spans and error responses.
+static INSTALLED: OnceLock<RedactionConfig> = OnceLock::new();
+
+/// Sets the config `redact_installed` uses. Only the first call counts.
+pub fn install(config: RedactionConfig) {
+    let _ = INSTALLED.set(config);
+}
+
+/// `redact_for_all` with the installed config. Text passes through until a
+/// config is installed.
+pub fn redact_installed<'a>(text: &'a str, targets: &[RedactionTarget]) -> Cow<'a, str> {
+    match INSTALLED.get() {
+        Some(config) => redact_for_all(text, config, targets),
+        None => Cow::Borrowed(text),
+    }
+}
+
+fn redact_with<'a>(text: &'a str, config: &RedactionConfig, policy: RedactionPolicy) -> Cow<'a, str> {
+    if policy == RedactionPolicy::Keep {
+        return Cow::Borrowed(text);
+    }
+
+    let matches = detect(text, &config.detectors);
+    if matches.is_empty() {
+        return Cow::Borrowed(text);
+    }
+
+    Cow::Owned(redact(text, &matches, policy, &config.hash_salt))
+}
+
+/// Finds PII in `text`. Detectors run in the order of `PiiKind::ALL` and a
+/// later match never overlaps an earlier one, so a card number is not also
+/// reported as a phone number.
+pub fn detect(text: &str, kinds: &[PiiKind]) -> Vec<PiiMatch> {
+    let mut found: Vec<PiiMatch> = Vec::new();
+
+    for kind in PiiKind::ALL.iter().filter(|k| kinds.contains(k)) {
+        for (start, end) in candidates(text, *kind) {
+            let overlaps = found.iter().any(|m| start < m.end && m.start < end);
+            if !overlaps {
+                found.push(PiiMatch { kind: *kind, start, end });
+            }
+        }
+    }
+
+    found.sort_by_key(|m| m.start);
+    found
+}
+
+fn candidates(text: &str, kind: PiiKind) -> Vec<(usize, usize)> {
+    let spans = |re: &Regex, valid: fn(&str) -> bool| {
+        re.find_iter(text)
+            .filter(|m| valid(m.as_str()))
+            .map(|m| (m.start(), m.end()))
+            .collect::<Vec<_>>()
+    };
+
+    match kind {
+        PiiKind::Email => spans(&EMAIL_RE, |_| true),
+        PiiKind::Iban => spans(&IBAN_RE, is_valid_iban),
+        PiiKind::CreditCard => spans(&CARD_RE, is_valid_card),
+        PiiKind::NationalId => {
+            let mut ids = spans(&TC_KIMLIK_RE, is_valid_tc_kimlik);
+            ids.extend(spans(&SSN_RE, is_valid_ssn));
+            ids
+        }
+        PiiKind::Phone => spans(&PHONE_RE, |s| {
+            let digits = s.chars().filter(char::is_ascii_digit).count();
+            (9..=15).contains(&digits)
+        }),
+    }
+}
+
+pub fn redact(text: &str, matches: &[PiiMatch], policy: RedactionPolicy, salt: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut cursor = 0;
+
+    for m in matches {
+        output.push_str(&text[cursor..m.start]);
+        match policy {
+            RedactionPolicy::Keep => output.push_str(&text[m.start..m.end]),
+            RedactionPolicy::Mask => {
+                output.push('[');
+                output.push_str(m.kind.label());
+                output.push(']');
+            }
+            RedactionPolicy::Hash => {
+                let digest = Sha256::digest(format!("{}{}", salt, &text[m.start..m.end]));
+                output.push_str(&format!("[{}:{}]", m.kind.label(), &hex::encode(digest)[..12]));
+            }
+            RedactionPolicy::Drop => {}
+        }
+        cursor = m.end;
+    }
+
+    output.push_str(&text[cursor..]);
+    output
+}
+
+fn digits(s: &str) -> Vec<u32> {
+    s.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+pub fn is_valid_card(s: &str) -> bool {
+    let digits = digits(s);
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+
+    // Luhn checksum
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                d
+            }
+        })
+        .sum();
+
+    sum % 10 == 0
+}
+
+pub fn is_valid_iban(s: &str) -> bool {
+    let compact: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    // Byte slicing below needs ASCII
+    if !(15..=34).contains(&compact.len()) || !compact.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+
+    // Move the country code and check digits to the end, map letters to
+    // 10..35 and check the remainder mod 97
+    let rearranged = compact[4..].chars().chain(compact[..4].chars());
+    let mut remainder: u32 = 0;
+    for c in rearranged {
+        let value = match c.to_digit(36) {
+            Some(v) => v,
+            None => return false,
+        };
+        let width = if value >= 10 { 100 } else { 10 };
+        remainder = (remainder * width + value) % 97;
+    }
+
+    remainder == 1
+}
+
+/// Turkish national identification number (T.C. Kimlik No).
+pub fn is_valid_tc_kimlik(s: &str) -> bool {
+    let d = digits(s);
+    if d.len() != 11 || d[0] == 0 {
+        return false;
+    }
+
+    let odd = d[0] + d[2] + d[4] + d[6] + d[8];
+    let even = d[1] + d[3] + d[5] + d[7];
+    let tenth = (odd * 7 + 10 * 9 - even) % 10;
+    let eleventh = d[..10].iter().sum::<u32>() % 10;
+
+    d[9] == tenth && d[10] == eleventh
+}
+
+/// US social security number; rejects the ranges that are never issued.
+pub fn is_valid_ssn(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('-').collect();
+    if parts.len() != 3 {
+        return false;
+    }
+
+    let area: u32 = parts[0].parse().unwrap_or(0);
+    area != 0 && area != 666 && area < 900 && parts[1] != "00" && parts[2] != "0000"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(policy: RedactionPolicy) -> RedactionConfig {
+        RedactionConfig {
+            enabled: true,
+            model_input: policy,
+            response_echo: policy,
+            logs: policy,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert!(is_valid_card("4111 1111 1111 1111"));
+        assert!(!is_valid_card("4111 1111 1111 1112"));
+        assert!(is_valid_iban("GB82 WEST 1234 5698 7654 32"));
+        assert!(is_valid_iban("TR330006100519786457841326"));
+        assert!(!is_valid_iban("GB82 WEST 1234 5698 7654 33"));
+        assert!(!is_valid_iban("€€€€€"));
+        assert!(is_valid_tc_kimlik("10000000146"));
+        assert!(!is_valid_tc_kimlik("10000000147"));
+        assert!(is_valid_ssn("123-45-6789"));
+        assert!(!is_valid_ssn("666-45-6789"));
+    }
+
+    #[test]
+    fn test_mask_policy() {
+        let text = "Card 4111-1111-1111-1111, mail a@b.com, call +90 532 123 45 67";
+        let redacted = redact_for(text, &config(RedactionPolicy::Mask), RedactionTarget::ModelInput);
+        assert_eq!(redacted, "Card [CREDIT_CARD], mail [EMAIL], call [PHONE]");
+    }
+
+    #[test]
+    fn test_invalid_checksum_is_not_a_card() {
+        let matches = detect("order 4111 1111 1111 1112", &[PiiKind::CreditCard]);
+        assert!(matches.is_empty());
+    }
+
+    #[test]
+    fn test_hash_is_stable_and_drop_removes() {
+        let cfg = config(RedactionPolicy::Hash);
+        let first = redact_for("id 10000000146", &cfg, RedactionTarget::Logs);
+        let second = redact_for("id 10000000146", &cfg, RedactionTarget::Logs);
+        assert_eq!(first, second);
+        assert!(first.starts_with("id [NATIONAL_ID:"));
+
+        let dropped = redact_for("iban GB82WEST12345698765432 ok", &config(RedactionPolicy::Drop), RedactionTarget::ResponseEcho);
+        assert_eq!(dropped, "iban  ok");
+    }
+
+    #[test]
+    fn test_echoed_input_uses_the_stricter_policy() {
+        let cfg = RedactionConfig {
+            enabled: true,
+            model_input: RedactionPolicy::Keep,
+            response_echo: RedactionPolicy::Mask,
+            ..Default::default()
+        };
+        let targets = [RedactionTarget::ModelInput, RedactionTarget::ResponseEcho];
+        assert_eq!(redact_for("mail a@b.com", &cfg, RedactionTarget::ModelInput), "mail a@b.com");
+        assert_eq!(redact_for_all("mail a@b.com", &cfg, &targets), "mail [EMAIL]");
+
+        let cfg = RedactionConfig { model_input: RedactionPolicy::Drop, ..cfg };
+        assert_eq!(redact_for_all("mail a@b.com", &cfg, &targets), "mail ");
+    }
+
+    #[test]
+    fn test_disabled_config_keeps_text() {
+        let cfg = RedactionConfig::default();
+        assert_eq!(redact_for("a@b.com", &cfg, RedactionTarget::Logs), "a@b.com");
+    }
+}
@@ -6,6 +6,9 @@ pub struct PredictRequest {
     pub text: String,
     #[serde(default)]
     pub model: Option<String>,
+    /// Include a per-stage latency breakdown in the response
+    #[serde(default)]
+    pub return_timings: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -13,24 +16,243 @@ pub struct BatchPredictRequest {
     pub texts: Vec<String>,
     #[serde(default)]
     pub model: Option<String>,
+    /// Include a per-stage latency breakdown in the response
+    #[serde(default)]
+    pub return_timings: bool,
 }
 
 #[derive(Debug, Serialize)]
 pub struct PredictResponse {
     pub success: bool,
-    pub result: Option<crate::inference::InferenceResult>,
-    pub error: Option<String>,
+    pub result: crate::inference::InferenceResult,
 }
 
 #[derive(Debug, Serialize)]
 pub struct BatchPredictResponse {
     pub success: bool,
-    pub results: Option<Vec<crate::inference::InferenceResult>>,
-    pub error: Option<String>,
+    pub results: Vec<crate::inference::InferenceResult>,
+    /// Queue wait once, plus every item's stages summed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<crate::inference::timings::StageTimings>,
 }
 
+/// Body of every error response, see `api::error::ApiError`.
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
+    pub success: bool,
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    /// Stable, machine-readable error code (`model_not_found`, `timeout`, ...)
+    pub code: &'static str,
+    pub message: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WarmupLine {
+    pub text: String,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WarmupResponse {
+    pub success: bool,
+    pub lines: usize,
+    pub warmed: usize,
+    pub already_cached: usize,
+    pub failed: usize,
+    pub errors: Vec<WarmupError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WarmupError {
+    pub line: usize,
+    pub code: &'static str,
     pub error: String,
-    pub code: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenizeRequest {
+    pub text: String,
+    #[serde(default)]
+    pub text_pair: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Run the model's redaction and preprocessing chain first
+    #[serde(default = "default_true")]
+    pub preprocess: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenizeResponse {
+    pub success: bool,
+    pub model: String,
+    /// Text that was tokenized, after preprocessing
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_pair: Option<String>,
+    pub ids: Vec<u32>,
+    pub tokens: Vec<String>,
+    /// Char offsets into `text` (or `text_pair`, see `sequence_ids`)
+    pub offsets: Vec<(usize, usize)>,
+    pub sequence_ids: Vec<Option<usize>>,
+    pub special_tokens_mask: Vec<u32>,
+    /// Tokens before truncation, special tokens included
+    pub num_tokens: usize,
+    pub max_length: usize,
+    pub exceeds_max_length: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DetokenizeRequest {
+    pub ids: Vec<u32>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default = "default_true")]
+    pub skip_special_tokens: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DetokenizeResponse {
+    pub success: bool,
+    pub model: String,
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatTemplateRequest {
+    pub messages: Vec<crate::preprocessing::chat_template::ChatMessage>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default = "default_true")]
+    pub add_generation_prompt: bool,
+    /// Extra template variables, e.g. `tools`
+    #[serde(default)]
+    pub context: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatTemplateResponse {
+    pub success: bool,
+    pub model: String,
+    pub template_source: crate::preprocessing::chat_template::ChatTemplateSource,
+    pub prompt: String,
+    pub num_tokens: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NerRequest {
+    pub text: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub aggregation_strategy: crate::inference::ner::AggregationStrategy,
+    /// Include a per-stage latency breakdown in the response
+    #[serde(default)]
+    pub return_timings: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NerResponse {
+    pub success: bool,
+    pub model: String,
+    /// Input after redaction; entity offsets are char offsets into it
+    pub text: String,
+    pub entities: Vec<crate::inference::ner::Entity>,
+    pub truncated_tokens: usize,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<crate::inference::timings::StageTimings>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QaRequest {
+    pub question: String,
+    pub context: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    /// Longest answer, in tokens
+    #[serde(default = "default_max_answer_len")]
+    pub max_answer_len: usize,
+    /// Include a per-stage latency breakdown in the response
+    #[serde(default)]
+    pub return_timings: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QaResponse {
+    pub success: bool,
+    pub model: String,
+    /// Context after redaction; answer offsets are char offsets into it
+    pub context: String,
+    pub answers: Vec<crate::inference::qa::Answer>,
+    pub num_windows: usize,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<crate::inference::timings::StageTimings>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZeroShotRequest {
+    pub text: String,
+    pub candidate_labels: Vec<String>,
+    #[serde(default = "default_hypothesis_template")]
+    pub hypothesis_template: String,
+    /// Score labels independently instead of as mutually exclusive classes
+    #[serde(default)]
+    pub multi_label: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Include a per-stage latency breakdown in the response
+    #[serde(default)]
+    pub return_timings: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZeroShotResponse {
+    pub success: bool,
+    pub model: String,
+    pub labels: Vec<String>,
+    pub scores: Vec<f32>,
+    pub multi_label: bool,
+    pub truncated_tokens: usize,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<crate::inference::timings::StageTimings>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogLevelRequest {
+    /// `EnvFilter` directives, e.g. `debug` or `info,transformer_forge::inference=trace`
+    pub level: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogLevelResponse {
+    pub success: bool,
+    pub level: String,
+}
+
+fn default_hypothesis_template() -> String {
+    crate::inference::zero_shot::DEFAULT_HYPOTHESIS_TEMPLATE.to_string()
+}
+
+fn default_top_k() -> usize {
+    1
+}
+
+fn default_max_answer_len() -> usize {
+    15
+}
+
+fn default_true() -> bool {
+    true
 }
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Waiting in the admission queue
+    QueueWait,
+    /// Redaction, normalization and the preprocessing chain
+    Preprocess,
+    /// Encoding, truncation and windowing
+    Tokenize,
+    /// Model forward passes
+    Forward,
+    /// Aggregating windows, decoding spans, scoring labels
+    Postprocess,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::QueueWait,
+        Stage::Preprocess,
+        Stage::Tokenize,
+        Stage::Forward,
+        Stage::Postprocess,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::QueueWait => "queue_wait",
+            Stage::Preprocess => "preprocess",
+            Stage::Tokenize => "tokenize",
+            Stage::Forward => "forward",
+            Stage::Postprocess => "postprocess",
+        }
+    }
+
+    /// Span around the stage, exported with the request's trace.
+    pub fn span(self) -> tracing::Span {
+        tracing::info_span!("inference_stage", stage = self.name())
+    }
+}
+
+/// Where a request's time went, in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StageTimings {
+    pub queue_wait_ms: f64,
+    pub preprocess_ms: f64,
+    pub tokenize_ms: f64,
+    pub forward_ms: f64,
+    pub postprocess_ms: f64,
+}
+
+impl StageTimings {
+    pub fn add(&mut self, stage: Stage, elapsed: Duration) {
+        *self.stage_mut(stage) += elapsed.as_secs_f64() * 1000.0;
+    }
+
+    /// Runs `f`, charging its time to `stage`.
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let output = stage.span().in_scope(f);
+        self.add(stage, start.elapsed());
+        output
+    }
+
+    pub fn get(&self, stage: Stage) -> f64 {
+        match stage {
+            Stage::QueueWait => self.queue_wait_ms,
+            Stage::Preprocess => self.preprocess_ms,
+            Stage::Tokenize => self.tokenize_ms,
+            Stage::Forward => self.forward_ms,
+            Stage::Postprocess => self.postprocess_ms,
+        }
+    }
+
+    /// Adds another request's stages to these, e.g. the items of a batch.
+    pub fn merge(&mut self, other: &StageTimings) {
+        for stage in Stage::ALL {
+            *self.stage_mut(stage) += other.get(stage);
+        }
+    }
+
+    fn stage_mut(&mut self, stage: Stage) -> &mut f64 {
+        match stage {
+            Stage::QueueWait => &mut self.queue_wait_ms,
+            Stage::Preprocess => &mut self.preprocess_ms,
+            Stage::Tokenize => &mut self.tokenize_ms,
+            Stage::Forward => &mut self.forward_ms,
+            Stage::Postprocess => &mut self.postprocess_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_timings_sum_stages() {
+        let mut first = StageTimings::default();
+        first.add(Stage::Forward, Duration::from_millis(30));
+        first.add(Stage::Forward, Duration::from_millis(5));
+        let second = StageTimings { forward_ms: 20.0, tokenize_ms: 2.0, ..Default::default() };
+
+        let mut batch = StageTimings { queue_wait_ms: 12.0, ..Default::default() };
+        batch.merge(&first);
+        batch.merge(&second);
+
+        assert_eq!(batch.queue_wait_ms, 12.0);
+        assert_eq!(batch.forward_ms, 55.0);
+        assert_eq!(batch.tokenize_ms, 2.0);
+    }
+}
@@ -1,56 +1,653 @@
 // Tokenizer implementation using tokenizers crate
 
-use tokenizers::Tokenizer;
+use tokenizers::decoders::{
+    byte_level::ByteLevel as ByteLevelDecoder, wordpiece::WordPiece as WordPieceDecoder, DecoderWrapper,
+};
+use tokenizers::models::{bpe::BPE, wordpiece::WordPiece, ModelWrapper};
+use tokenizers::normalizers::{bert::BertNormalizer, NormalizerWrapper};
+use tokenizers::pre_tokenizers::{bert::BertPreTokenizer, byte_level::ByteLevel, PreTokenizerWrapper};
+use tokenizers::processors::{bert::BertProcessing, roberta::RobertaProcessing, PostProcessorWrapper};
+use tokenizers::{
+    Encoding, PaddingDirection, PaddingParams, PaddingStrategy, PostProcessor, Tokenizer,
+    TruncationDirection, TruncationParams, TruncationStrategy,
+};
 use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
 use std::path::Path;
 
+use super::chat_template::template_from_tokenizer_config;
+use crate::inference::InferenceError;
+
+/// Hugging Face's placeholder for "no limit" is 1e30; anything above this is ignored.
+const MAX_SANE_MODEL_LENGTH: f64 = 1_000_000.0;
+
+/// Size of the id space used by the whitespace fallback.
+const FALLBACK_VOCAB_SIZE: u32 = 30_000;
+
+/// Settings read from `tokenizer_config.json` / `special_tokens_map.json`.
+#[derive(Debug, Clone, Default)]
+pub struct TokenizerSettings {
+    pub model_max_length: Option<usize>,
+    pub do_lower_case: Option<bool>,
+    pub padding_side: Option<String>,
+    pub truncation_side: Option<String>,
+    pub cls_token: Option<String>,
+    pub sep_token: Option<String>,
+    pub pad_token: Option<String>,
+    pub unk_token: Option<String>,
+    pub bos_token: Option<String>,
+    pub eos_token: Option<String>,
+    pub chat_template: Option<String>,
+}
+
+impl TokenizerSettings {
+    pub fn load(model_dir: &Path) -> Result<Self> {
+        let mut settings = Self::default();
+
+        // tokenizer_config.json wins over special_tokens_map.json
+        for file in ["special_tokens_map.json", "tokenizer_config.json"] {
+            let path = model_dir.join(file);
+            if path.exists() {
+                let value: Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+                settings.merge(&value);
+            }
+        }
+
+        Ok(settings)
+    }
+
+    fn merge(&mut self, value: &Value) {
+        if let Some(max) = value.get("model_max_length").and_then(Value::as_f64) {
+            if max > 0.0 && max < MAX_SANE_MODEL_LENGTH {
+                self.model_max_length = Some(max as usize);
+            }
+        }
+
+        let string = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+
+        if let Some(lower) = value.get("do_lower_case").and_then(Value::as_bool) {
+            self.do_lower_case = Some(lower);
+        }
+        self.padding_side = string("padding_side").or(self.padding_side.take());
+        self.truncation_side = string("truncation_side").or(self.truncation_side.take());
+
+        self.cls_token = special_token(value, "cls_token").or(self.cls_token.take());
+        self.sep_token = special_token(value, "sep_token").or(self.sep_token.take());
+        self.pad_token = special_token(value, "pad_token").or(self.pad_token.take());
+        self.unk_token = special_token(value, "unk_token").or(self.unk_token.take());
+        self.bos_token = special_token(value, "bos_token").or(self.bos_token.take());
+        self.eos_token = special_token(value, "eos_token").or(self.eos_token.take());
+        self.chat_template = template_from_tokenizer_config(value).or(self.chat_template.take());
+    }
+}
+
+/// Special tokens are either a plain string or an `AddedToken` object.
+fn special_token(value: &Value, key: &str) -> Option<String> {
+    match value.get(key)? {
+        Value::String(token) => Some(token.clone()),
+        Value::Object(token) => token.get("content").and_then(Value::as_str).map(str::to_string),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenizerSource {
+    TokenizerJson,
+    WordPiece,
+    Bpe,
+    Whitespace,
+}
+
+/// Model-ready encoding of a text or text pair. Offsets are byte ranges into
+/// the sequence the token came from (see `sequence_ids`); special tokens
+/// have `(0, 0)` and no sequence id.
+#[derive(Debug, Clone, Serialize)]
+pub struct EncodedInput {
+    pub ids: Vec<u32>,
+    pub tokens: Vec<String>,
+    pub offsets: Vec<(usize, usize)>,
+    pub sequence_ids: Vec<Option<usize>>,
+    /// Index of the word each token belongs to, per sequence
+    pub word_ids: Vec<Option<u32>>,
+    pub attention_mask: Vec<u32>,
+    pub type_ids: Vec<u32>,
+    pub special_tokens_mask: Vec<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub overflowing: Vec<EncodedInput>,
+}
+
+impl EncodedInput {
+    fn from_encoding(encoding: &Encoding) -> Self {
+        Self {
+            ids: encoding.get_ids().to_vec(),
+            tokens: encoding.get_tokens().to_vec(),
+            offsets: encoding.get_offsets().to_vec(),
+            sequence_ids: encoding.get_sequence_ids(),
+            word_ids: encoding.get_word_ids().to_vec(),
+            attention_mask: encoding.get_attention_mask().to_vec(),
+            type_ids: encoding.get_type_ids().to_vec(),
+            special_tokens_mask: encoding.get_special_tokens_mask().to_vec(),
+            overflowing: encoding.get_overflowing().iter().map(Self::from_encoding).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Cuts the encoding down to `max_length` tokens, keeping a trailing
+    /// special token ([SEP], </s>) in place.
+    pub fn truncate_to(&mut self, max_length: usize) -> usize {
+        let len = self.len();
+        if len <= max_length || max_length == 0 {
+            return 0;
+        }
+
+        let keep_last = self.special_tokens_mask.last() == Some(&1);
+        let head = if keep_last { max_length - 1 } else { max_length };
+
+        fn cut<T: Clone>(values: &mut Vec<T>, head: usize, keep_last: bool) {
+            let last = values.last().cloned();
+            values.truncate(head);
+            if let (true, Some(last)) = (keep_last, last) {
+                values.push(last);
+            }
+        }
+
+        cut(&mut self.ids, head, keep_last);
+        cut(&mut self.tokens, head, keep_last);
+        cut(&mut self.offsets, head, keep_last);
+        cut(&mut self.sequence_ids, head, keep_last);
+        cut(&mut self.word_ids, head, keep_last);
+        cut(&mut self.attention_mask, head, keep_last);
+        cut(&mut self.type_ids, head, keep_last);
+        cut(&mut self.special_tokens_mask, head, keep_last);
+
+        len - max_length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
 pub struct CustomTokenizer {
+    /// Tokenizer with the truncation/padding from tokenizer_config.json
     tokenizer: Option<Tokenizer>,
+    /// Same tokenizer without truncation, used to measure and window inputs
+    untruncated: Option<Tokenizer>,
+    settings: TokenizerSettings,
+    source: TokenizerSource,
 }
 
 impl CustomTokenizer {
     pub fn new() -> Self {
-        Self { tokenizer: None }
+        Self {
+            tokenizer: None,
+            untruncated: None,
+            settings: TokenizerSettings::default(),
+            source: TokenizerSource::Whitespace,
+        }
     }
 
     pub fn load_from_file(path: &Path) -> Result<Self> {
         let tokenizer = Tokenizer::from_file(path)
             .map_err(|e| anyhow::anyhow!("Failed to load tokenizer: {}", e))?;
-        
+
+        Self::from_tokenizer(tokenizer, TokenizerSettings::default(), TokenizerSource::TokenizerJson)
+    }
+
+    /// Loads a tokenizer from a model directory: `tokenizer.json` if present,
+    /// otherwise a WordPiece tokenizer from `vocab.txt` or a byte-level BPE
+    /// from `vocab.json` + `merges.txt`. Falls back to whitespace tokenization
+    /// when none of them exist.
+    pub fn load_from_dir(model_dir: &Path) -> Result<Self> {
+        let settings = TokenizerSettings::load(model_dir)?;
+
+        let tokenizer_json = model_dir.join("tokenizer.json");
+        let vocab_txt = model_dir.join("vocab.txt");
+        let vocab_json = model_dir.join("vocab.json");
+        let merges_txt = model_dir.join("merges.txt");
+
+        let (tokenizer, source) = if tokenizer_json.exists() {
+            let tokenizer = Tokenizer::from_file(&tokenizer_json)
+                .map_err(|e| anyhow::anyhow!("Failed to load tokenizer: {}", e))?;
+            (tokenizer, TokenizerSource::TokenizerJson)
+        } else if vocab_txt.exists() {
+            (build_wordpiece(&vocab_txt, &settings)?, TokenizerSource::WordPiece)
+        } else if vocab_json.exists() && merges_txt.exists() {
+            (build_bpe(&vocab_json, &merges_txt, &settings)?, TokenizerSource::Bpe)
+        } else {
+            tracing::warn!("No tokenizer files in {:?}, using whitespace tokenizer", model_dir);
+            return Ok(Self { settings, ..Self::new() });
+        };
+
+        tracing::info!("Tokenizer loaded from {:?} ({:?})", model_dir, source);
+        Self::from_tokenizer(tokenizer, settings, source)
+    }
+
+    fn from_tokenizer(tokenizer: Tokenizer, settings: TokenizerSettings, source: TokenizerSource) -> Result<Self> {
+        let mut untruncated = tokenizer.clone();
+        untruncated
+            .with_truncation(None)
+            .map_err(|e| anyhow::anyhow!("Failed to configure tokenizer: {}", e))?;
+        untruncated.with_padding(None);
+
+        let mut configured = tokenizer;
+        if let Some(max_length) = settings.model_max_length {
+            let direction = match settings.truncation_side.as_deref() {
+                Some("left") => TruncationDirection::Left,
+                _ => TruncationDirection::Right,
+            };
+            configured
+                .with_truncation(Some(TruncationParams {
+                    max_length,
+                    direction,
+                    strategy: TruncationStrategy::LongestFirst,
+                    stride: 0,
+                }))
+                .map_err(|e| anyhow::anyhow!("Failed to configure truncation: {}", e))?;
+        }
+
+        if let Some(pad_token) = &settings.pad_token {
+            if let Some(pad_id) = configured.token_to_id(pad_token) {
+                let direction = match settings.padding_side.as_deref() {
+                    Some("left") => PaddingDirection::Left,
+                    _ => PaddingDirection::Right,
+                };
+                // Only batches are padded, to their longest member
+                configured.with_padding(Some(PaddingParams {
+                    strategy: PaddingStrategy::BatchLongest,
+                    direction,
+                    pad_to_multiple_of: None,
+                    pad_id,
+                    pad_type_id: 0,
+                    pad_token: pad_token.clone(),
+                }));
+            }
+        }
+
         Ok(Self {
-            tokenizer: Some(tokenizer),
+            tokenizer: Some(configured),
+            untruncated: Some(untruncated),
+            settings,
+            source,
         })
     }
 
-    pub fn encode(&self, text: &str) -> Result<Vec<u32>> {
-        if let Some(tokenizer) = &self.tokenizer {
+    pub fn source(&self) -> TokenizerSource {
+        self.source
+    }
+
+    pub fn settings(&self) -> &TokenizerSettings {
+        &self.settings
+    }
+
+    /// The model's own token limit from tokenizer_config.json, if it has one.
+    pub fn max_length(&self) -> Option<usize> {
+        self.settings.model_max_length
+    }
+
+    /// Number of special tokens the post-processor adds to a sequence or pair.
+    pub fn num_special_tokens_for(&self, is_pair: bool) -> usize {
+        self.tokenizer
+            .as_ref()
+            .and_then(|tokenizer| tokenizer.get_post_processor())
+            .map(|processor| processor.added_tokens(is_pair))
+            .unwrap_or(0)
+    }
+
+    pub fn num_special_tokens(&self) -> usize {
+        self.num_special_tokens_for(false)
+    }
+
+    /// Encodes without special tokens or truncation and returns the ids with
+    /// their byte offsets into `text`.
+    pub fn encode_with_offsets(&self, text: &str) -> Result<(Vec<u32>, Vec<(usize, usize)>)> {
+        if let Some(tokenizer) = &self.untruncated {
             let encoding = tokenizer
                 .encode(text, false)
                 .map_err(|e| anyhow::anyhow!("Encoding failed: {}", e))?;
-            
-            Ok(encoding.get_ids().to_vec())
+
+            Ok((encoding.get_ids().to_vec(), encoding.get_offsets().to_vec()))
         } else {
-            // Fallback: simple whitespace tokenization
-            Ok(self.simple_tokenize(text))
+            let offsets = fallback_offsets(text);
+            let ids = offsets.iter().map(|&(s, e)| fallback_id(&text[s..e])).collect();
+            Ok((ids, offsets))
+        }
+    }
+
+    /// Token ids for `text` with special tokens ([CLS]/[SEP], <s>/</s>, ...).
+    pub fn encode(&self, text: &str) -> Result<Vec<u32>> {
+        Ok(self.encode_input(text)?.ids)
+    }
+
+    pub fn encode_input(&self, text: &str) -> Result<EncodedInput> {
+        match &self.tokenizer {
+            Some(tokenizer) => {
+                let encoding = tokenizer
+                    .encode(text, true)
+                    .map_err(|e| anyhow::anyhow!("Encoding failed: {}", e))?;
+                Ok(EncodedInput::from_encoding(&encoding))
+            }
+            None => Ok(self.fallback_encode(&[text])),
+        }
+    }
+
+    /// Like `encode_input`/`encode_pair` but without the truncation from
+    /// tokenizer_config.json, so the result shows the full token count.
+    pub fn encode_untruncated(&self, first: &str, second: Option<&str>) -> Result<EncodedInput> {
+        match &self.untruncated {
+            Some(tokenizer) => {
+                let encoding = match second {
+                    Some(second) => tokenizer.encode((first, second), true),
+                    None => tokenizer.encode(first, true),
+                }
+                .map_err(|e| anyhow::anyhow!("Encoding failed: {}", e))?;
+                Ok(EncodedInput::from_encoding(&encoding))
+            }
+            None => Ok(self.fallback_encode(&[Some(first), second].into_iter().flatten().collect::<Vec<_>>())),
+        }
+    }
+
+    /// Encodes a sentence pair (NLI premise/hypothesis, QA question/context).
+    pub fn encode_pair(&self, first: &str, second: &str) -> Result<EncodedInput> {
+        match &self.tokenizer {
+            Some(tokenizer) => {
+                let encoding = tokenizer
+                    .encode((first, second), true)
+                    .map_err(|e| anyhow::anyhow!("Encoding failed: {}", e))?;
+                Ok(EncodedInput::from_encoding(&encoding))
+            }
+            None => Ok(self.fallback_encode(&[first, second])),
+        }
+    }
+
+    /// Encodes `first` paired with overlapping windows of `second`, each
+    /// window fitting `max_length` with special tokens; consecutive windows
+    /// share `stride` tokens of `second` (QA question/context).
+    pub fn encode_pair_windows(
+        &self,
+        first: &str,
+        second: &str,
+        max_length: usize,
+        stride: usize,
+    ) -> Result<Vec<EncodedInput>> {
+        match &self.untruncated {
+            Some(tokenizer) => {
+                let question = tokenizer
+                    .encode(first, false)
+                    .map_err(|e| anyhow::anyhow!("Encoding failed: {}", e))?;
+                let mut context = tokenizer
+                    .encode(second, false)
+                    .map_err(|e| anyhow::anyhow!("Encoding failed: {}", e))?;
+
+                let budget = window_budget(max_length, question.len() + self.num_special_tokens_for(true))?;
+                context.truncate(budget, stride.min(budget - 1), TruncationDirection::Right);
+                let overflowing = context.take_overflowing();
+
+                std::iter::once(context)
+                    .chain(overflowing)
+                    .map(|window| {
+                        tokenizer
+                            .post_process(question.clone(), Some(window), true)
+                            .map(|encoding| EncodedInput::from_encoding(&encoding))
+                            .map_err(|e| anyhow::anyhow!("Encoding failed: {}", e))
+                    })
+                    .collect()
+            }
+            None => {
+                let question = self.fallback_encode(&[first]);
+                let context = fallback_offsets(second);
+                let budget = window_budget(max_length, question.len())?;
+
+                Ok(window_ranges(context.len(), budget, stride.min(budget - 1))
+                    .into_iter()
+                    .map(|(start, end)| {
+                        let mut encoded = question.clone();
+                        for (word, &(s, e)) in context.iter().enumerate().take(end).skip(start) {
+                            encoded.ids.push(fallback_id(&second[s..e]));
+                            encoded.tokens.push(second[s..e].to_string());
+                            encoded.offsets.push((s, e));
+                            encoded.sequence_ids.push(Some(1));
+                            encoded.word_ids.push(Some(word as u32));
+                            encoded.attention_mask.push(1);
+                            encoded.type_ids.push(1);
+                            encoded.special_tokens_mask.push(0);
+                        }
+                        encoded
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Encodes several texts at once, padded to the longest one.
+    pub fn encode_batch(&self, texts: &[&str]) -> Result<Vec<EncodedInput>> {
+        match &self.tokenizer {
+            Some(tokenizer) => {
+                let encodings = tokenizer
+                    .encode_batch(texts.to_vec(), true)
+                    .map_err(|e| anyhow::anyhow!("Encoding failed: {}", e))?;
+                Ok(encodings.iter().map(EncodedInput::from_encoding).collect())
+            }
+            None => Ok(texts.iter().map(|text| self.fallback_encode(&[text])).collect()),
+        }
+    }
+
+    /// Whitespace/punctuation split with stable hashed ids. There is no
+    /// vocabulary behind them: they are only good for counting and caching.
+    fn fallback_encode(&self, sequences: &[&str]) -> EncodedInput {
+        let mut encoded = EncodedInput {
+            ids: Vec::new(),
+            tokens: Vec::new(),
+            offsets: Vec::new(),
+            sequence_ids: Vec::new(),
+            word_ids: Vec::new(),
+            attention_mask: Vec::new(),
+            type_ids: Vec::new(),
+            special_tokens_mask: Vec::new(),
+            overflowing: Vec::new(),
+        };
+
+        for (sequence, text) in sequences.iter().enumerate() {
+            for (word, (start, end)) in fallback_offsets(text).into_iter().enumerate() {
+                encoded.ids.push(fallback_id(&text[start..end]));
+                encoded.tokens.push(text[start..end].to_string());
+                encoded.offsets.push((start, end));
+                encoded.sequence_ids.push(Some(sequence));
+                encoded.word_ids.push(Some(word as u32));
+                encoded.attention_mask.push(1);
+                encoded.type_ids.push(sequence as u32);
+                encoded.special_tokens_mask.push(0);
+            }
         }
+
+        encoded
     }
 
-    fn simple_tokenize(&self, text: &str) -> Vec<u32> {
-        text.split_whitespace()
-            .enumerate()
-            .map(|(i, _)| i as u32)
-            .collect()
+    /// Whether ids map back to tokens; the whitespace fallback can't decode.
+    pub fn has_vocabulary(&self) -> bool {
+        self.tokenizer.is_some()
     }
 
     pub fn decode(&self, ids: &[u32]) -> Result<String> {
+        self.decode_with(ids, false)
+    }
+
+    pub fn decode_with(&self, ids: &[u32], skip_special_tokens: bool) -> Result<String> {
         if let Some(tokenizer) = &self.tokenizer {
             tokenizer
-                .decode(ids, false)
+                .decode(ids, skip_special_tokens)
                 .map_err(|e| anyhow::anyhow!("Decoding failed: {}", e))
         } else {
-            Ok(format!("tokens: {:?}", ids))
+            anyhow::bail!("Decoding needs a vocabulary, but this model has no tokenizer files")
         }
     }
+
+    pub fn id_to_token(&self, id: u32) -> Option<String> {
+        self.tokenizer.as_ref().and_then(|tokenizer| tokenizer.id_to_token(id))
+    }
+
+    pub fn token_to_id(&self, token: &str) -> Option<u32> {
+        self.tokenizer.as_ref().and_then(|tokenizer| tokenizer.token_to_id(token))
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        self.tokenizer
+            .as_ref()
+            .map(|tokenizer| tokenizer.get_vocab_size(true))
+            .unwrap_or(FALLBACK_VOCAB_SIZE as usize)
+    }
+}
+
+/// Byte to char offset lookup for one text, built in a single pass so
+/// converting every token's offsets stays linear.
+pub struct CharIndex {
+    /// Char index of the char each byte belongs to, plus the char count
+    /// for the end of the text
+    chars: Vec<usize>,
+}
+
+impl CharIndex {
+    pub fn new(text: &str) -> Self {
+        let mut chars = Vec::with_capacity(text.len() + 1);
+        let mut count = 0;
+        for c in text.chars() {
+            chars.extend(std::iter::repeat(count).take(c.len_utf8()));
+            count += 1;
+        }
+        chars.push(count);
+        Self { chars }
+    }
+
+    /// Char offset of `byte`. Bytes inside a char map to that char; bytes
+    /// past the end map to the end.
+    pub fn char_offset(&self, byte: usize) -> usize {
+        self.chars[byte.min(self.chars.len() - 1)]
+    }
+
+    /// Converts a `(start, end)` byte range into char offsets.
+    pub fn char_range(&self, (start, end): (usize, usize)) -> (usize, usize) {
+        (self.char_offset(start), self.char_offset(end))
+    }
+}
+
+/// Tokens left for the second sequence once `used` tokens are taken.
+fn window_budget(max_length: usize, used: usize) -> Result<usize> {
+    match max_length.checked_sub(used) {
+        Some(budget) if budget > 0 => Ok(budget),
+        _ => Err(InferenceError::InputTooLong {
+            length: used,
+            limit: max_length,
+            unit: "tokens",
+        }
+        .into()),
+    }
+}
+
+/// Token ranges of `len` tokens in windows of `size`, each overlapping the
+/// previous one by `stride`. Mirrors `Encoding::truncate` with a stride.
+fn window_ranges(len: usize, size: usize, stride: usize) -> Vec<(usize, usize)> {
+    let step = size.saturating_sub(stride).max(1);
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + size).min(len);
+        ranges.push((start, end));
+        if end == len {
+            return ranges;
+        }
+        start += step;
+    }
+}
+
+fn build_wordpiece(vocab: &Path, settings: &TokenizerSettings) -> Result<Tokenizer> {
+    let unk = settings.unk_token.clone().unwrap_or_else(|| "[UNK]".to_string());
+    let cls = settings.cls_token.clone().unwrap_or_else(|| "[CLS]".to_string());
+    let sep = settings.sep_token.clone().unwrap_or_else(|| "[SEP]".to_string());
+
+    let model = WordPiece::from_file(&vocab.to_string_lossy())
+        .unk_token(unk)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to build WordPiece from {:?}: {}", vocab, e))?;
+
+    let mut tokenizer = Tokenizer::new(ModelWrapper::WordPiece(model));
+    let lowercase = settings.do_lower_case.unwrap_or(true);
+    tokenizer.with_normalizer(NormalizerWrapper::BertNormalizer(BertNormalizer::new(
+        true, true, None, lowercase,
+    )));
+    tokenizer.with_pre_tokenizer(PreTokenizerWrapper::BertPreTokenizer(BertPreTokenizer));
+    tokenizer.with_decoder(DecoderWrapper::WordPiece(WordPieceDecoder::default()));
+
+    let cls_id = tokenizer.token_to_id(&cls);
+    let sep_id = tokenizer.token_to_id(&sep);
+    if let (Some(cls_id), Some(sep_id)) = (cls_id, sep_id) {
+        tokenizer.with_post_processor(PostProcessorWrapper::Bert(BertProcessing::new(
+            (sep, sep_id),
+            (cls, cls_id),
+        )));
+    }
+
+    Ok(tokenizer)
+}
+
+fn build_bpe(vocab: &Path, merges: &Path, settings: &TokenizerSettings) -> Result<Tokenizer> {
+    let model = BPE::from_file(&vocab.to_string_lossy(), &merges.to_string_lossy())
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to build BPE from {:?}: {}", vocab, e))?;
+
+    let mut tokenizer = Tokenizer::new(ModelWrapper::BPE(model));
+    tokenizer.with_pre_tokenizer(PreTokenizerWrapper::ByteLevel(ByteLevel::default()));
+    tokenizer.with_decoder(DecoderWrapper::ByteLevel(ByteLevelDecoder::default()));
+
+    // RoBERTa-style models wrap sequences in <s> ... </s>; GPT-2 adds nothing
+    if let (Some(cls), Some(sep)) = (settings.cls_token.clone(), settings.sep_token.clone()) {
+        if let (Some(cls_id), Some(sep_id)) = (tokenizer.token_to_id(&cls), tokenizer.token_to_id(&sep)) {
+            tokenizer.with_post_processor(PostProcessorWrapper::Roberta(RobertaProcessing::new(
+                (sep, sep_id),
+                (cls, cls_id),
+            )));
+        }
+    }
+
+    Ok(tokenizer)
+}
+
+/// Splits on whitespace and keeps punctuation as separate tokens, like
+/// BERT's basic pre-tokenizer. Returns byte offsets.
+fn fallback_offsets(text: &str) -> Vec<(usize, usize)> {
+    let mut offsets = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() || c.is_ascii_punctuation() {
+            if let Some(s) = start.take() {
+                offsets.push((s, i));
+            }
+            if c.is_ascii_punctuation() {
+                offsets.push((i, i + c.len_utf8()));
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+
+    if let Some(s) = start {
+        offsets.push((s, text.len()));
+    }
+
+    offsets
+}
+
+/// FNV-1a hash of the token, folded into the fallback id space.
+fn fallback_id(token: &str) -> u32 {
+    let hash = token.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+    hash % FALLBACK_VOCAB_SIZE
 }
 
 #[cfg(test)]
@@ -63,4 +660,104 @@ mod tests {
         let tokens = tokenizer.encode("Hello World").unwrap();
         assert!(!tokens.is_empty());
     }
+
+    #[test]
+    fn test_fallback_ids_are_stable_and_offsets_split_punctuation() {
+        let tokenizer = CustomTokenizer::new();
+        let first = tokenizer.encode_input("Hello, World!").unwrap();
+        let second = tokenizer.encode_input("World").unwrap();
+
+        assert_eq!(first.tokens, vec!["Hello", ",", "World", "!"]);
+        assert_eq!(first.offsets, vec![(0, 5), (5, 6), (7, 12), (12, 13)]);
+        assert_eq!(first.ids[2], second.ids[0]);
+        // Hashed ids can't be turned back into text
+        assert!(!tokenizer.has_vocabulary());
+        assert!(tokenizer.decode(&first.ids).is_err());
+    }
+
+    #[test]
+    fn test_char_index() {
+        let chars = CharIndex::new("güzel ürün");
+        assert_eq!(chars.char_range((0, 6)), (0, 5));
+        assert_eq!(chars.char_range((7, 13)), (6, 10));
+        // Inside the two-byte "ü", and past the end
+        assert_eq!(chars.char_offset(2), 1);
+        assert_eq!(chars.char_offset(100), 10);
+    }
+
+    #[test]
+    fn test_fallback_pair_sets_type_ids() {
+        let tokenizer = CustomTokenizer::new();
+        let pair = tokenizer.encode_pair("who?", "me").unwrap();
+        assert_eq!(pair.type_ids, vec![0, 0, 1]);
+        assert_eq!(pair.sequence_ids, vec![Some(0), Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn test_wordpiece_from_vocab_adds_special_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("vocab.txt"),
+            "[PAD]\n[UNK]\n[CLS]\n[SEP]\nhello\nworld\n##s\n",
+        )
+        .unwrap();
+
+        let tokenizer = CustomTokenizer::load_from_dir(dir.path()).unwrap();
+        assert_eq!(tokenizer.source(), TokenizerSource::WordPiece);
+
+        let encoded = tokenizer.encode_input("Hello worlds").unwrap();
+        assert_eq!(encoded.tokens, vec!["[CLS]", "hello", "world", "##s", "[SEP]"]);
+        assert_eq!(encoded.special_tokens_mask, vec![1, 0, 0, 0, 1]);
+        assert_eq!(encoded.offsets[3], (11, 12));
+
+        assert_eq!(encoded.word_ids, vec![None, Some(0), Some(1), Some(1), None]);
+
+        let pair = tokenizer.encode_pair("hello", "world").unwrap();
+        assert_eq!(pair.type_ids, vec![0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_truncate_to_keeps_trailing_special_token() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("vocab.txt"), "[UNK]\n[CLS]\n[SEP]\na\nb\nc\n").unwrap();
+        let tokenizer = CustomTokenizer::load_from_dir(dir.path()).unwrap();
+
+        let mut encoded = tokenizer.encode_input("a b c").unwrap();
+        assert_eq!(encoded.truncate_to(4), 1);
+        assert_eq!(encoded.tokens, vec!["[CLS]", "a", "b", "[SEP]"]);
+        assert_eq!(encoded.special_tokens_mask, vec![1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_pair_windows_overlap_by_stride() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("vocab.txt"), "[UNK]\n[CLS]\n[SEP]\nwho\na\nb\nc\nd\ne\n").unwrap();
+        let tokenizer = CustomTokenizer::load_from_dir(dir.path()).unwrap();
+
+        let windows = tokenizer.encode_pair_windows("who", "a b c d e", 6, 1).unwrap();
+        assert_eq!(windows.len(), 4);
+        assert_eq!(windows[1].tokens, vec!["[CLS]", "who", "[SEP]", "b", "c", "[SEP]"]);
+        assert_eq!(windows[1].offsets[3], (2, 3));
+        assert_eq!(windows[1].sequence_ids[3], Some(1));
+
+        // Same windows without a vocabulary
+        let fallback = CustomTokenizer::new().encode_pair_windows("who", "a b c d e", 3, 1).unwrap();
+        let tokens: Vec<_> = fallback.iter().map(|w| w.tokens[1..].join(" ")).collect();
+        assert_eq!(tokens, vec!["a b", "b c", "c d", "d e"]);
+
+        assert!(tokenizer.encode_pair_windows("who", "a", 3, 0).is_err());
+    }
+
+    #[test]
+    fn test_settings_read_added_token_objects() {
+        let mut settings = TokenizerSettings::default();
+        settings.merge(&serde_json::json!({
+            "model_max_length": 1e30,
+            "cls_token": {"content": "<s>", "lstrip": false},
+            "truncation_side": "left"
+        }));
+        assert_eq!(settings.model_max_length, None);
+        assert_eq!(settings.cls_token.as_deref(), Some("<s>"));
+        assert_eq!(settings.truncation_side.as_deref(), Some("left"));
+    }
 }
@@ -0,0 +1,221 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+use super::tokenizer::CustomTokenizer;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncationStrategy {
+    /// Keep the first tokens
+    #[default]
+    Head,
+    /// Keep the last tokens
+    Tail,
+    /// Keep the first and last tokens, dropping the middle
+    HeadTail,
+    /// Split the input into overlapping windows that cover all of it
+    SlidingWindow,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowAggregation {
+    #[default]
+    Mean,
+    Max,
+    Vote,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TruncationConfig {
+    #[serde(default)]
+    pub strategy: TruncationStrategy,
+    /// Number of tokens shared by consecutive sliding windows
+    #[serde(default = "default_stride")]
+    pub stride: usize,
+    #[serde(default)]
+    pub aggregation: WindowAggregation,
+    /// Upper bound on sliding windows per input; the rest is truncated
+    #[serde(default = "default_max_windows")]
+    pub max_windows: usize,
+}
+
+fn default_stride() -> usize {
+    128
+}
+
+fn default_max_windows() -> usize {
+    16
+}
+
+impl Default for TruncationConfig {
+    fn default() -> Self {
+        Self {
+            strategy: TruncationStrategy::default(),
+            stride: default_stride(),
+            aggregation: WindowAggregation::default(),
+            max_windows: default_max_windows(),
+        }
+    }
+}
+
+/// A piece of the input that fits in the model's token budget.
+#[derive(Debug, Clone)]
+pub struct TextWindow {
+    pub text: String,
+    pub num_tokens: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TruncatedText {
+    pub windows: Vec<TextWindow>,
+    pub total_tokens: usize,
+    pub truncated_tokens: usize,
+}
+
+/// Tokenizes `text` and cuts it down to `max_length` tokens (special tokens
+/// included) using the configured strategy. Windows are mapped back to the
+/// original text through the tokenizer offsets, so no UTF-8 boundary is split.
+pub fn truncate_text(
+    text: &str,
+    tokenizer: &CustomTokenizer,
+    max_length: usize,
+    config: &TruncationConfig,
+) -> Result<TruncatedText> {
+    let (_, offsets) = tokenizer.encode_with_offsets(text)?;
+    let budget = max_length.saturating_sub(tokenizer.num_special_tokens()).max(1);
+
+    let (plans, truncated_tokens) = plan_windows(offsets.len(), budget, config);
+
+    let windows = if plans.is_empty() {
+        vec![TextWindow { text: text.to_string(), num_tokens: 0 }]
+    } else {
+        plans
+            .into_iter()
+            .map(|ranges| {
+                let num_tokens = ranges.iter().map(|r| r.len()).sum();
+                let text = ranges
+                    .iter()
+                    .map(|r| &text[offsets[r.start].0..offsets[r.end - 1].1])
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                TextWindow { text, num_tokens }
+            })
+            .collect()
+    };
+
+    Ok(TruncatedText {
+        windows,
+        total_tokens: offsets.len(),
+        truncated_tokens,
+    })
+}
+
+/// Decides which token ranges make up each window. Returns the windows and
+/// the number of tokens that ended up in none of them.
+pub fn plan_windows(
+    num_tokens: usize,
+    budget: usize,
+    config: &TruncationConfig,
+) -> (Vec<Vec<Range<usize>>>, usize) {
+    if num_tokens == 0 {
+        return (Vec::new(), 0);
+    }
+
+    if num_tokens <= budget {
+        return (vec![vec![0..num_tokens]], 0);
+    }
+
+    let truncated = num_tokens - budget;
+
+    match config.strategy {
+        TruncationStrategy::Head => (vec![vec![0..budget]], truncated),
+        TruncationStrategy::Tail => (vec![vec![num_tokens - budget..num_tokens]], truncated),
+        TruncationStrategy::HeadTail => {
+            let head = budget.div_ceil(2);
+            let tail = budget - head;
+            let mut ranges = vec![0..head];
+            if tail > 0 {
+                ranges.push(num_tokens - tail..num_tokens);
+            }
+            (vec![ranges], truncated)
+        }
+        TruncationStrategy::SlidingWindow => {
+            let step = budget.saturating_sub(config.stride).max(1);
+            let max_windows = config.max_windows.max(1);
+            let mut windows = Vec::new();
+            let mut start = 0;
+
+            loop {
+                let end = (start + budget).min(num_tokens);
+                windows.push(vec![start..end]);
+
+                if end == num_tokens || windows.len() == max_windows {
+                    return (windows, num_tokens - end);
+                }
+                start += step;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(strategy: TruncationStrategy, stride: usize) -> TruncationConfig {
+        TruncationConfig {
+            strategy,
+            stride,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_short_input_is_untouched() {
+        let (windows, truncated) = plan_windows(5, 10, &config(TruncationStrategy::Head, 0));
+        assert_eq!(windows, vec![vec![0..5]]);
+        assert_eq!(truncated, 0);
+    }
+
+    #[test]
+    fn test_head_tail_and_head_tail() {
+        let (head, truncated) = plan_windows(10, 4, &config(TruncationStrategy::Head, 0));
+        assert_eq!(head, vec![vec![0..4]]);
+        assert_eq!(truncated, 6);
+
+        let (tail, _) = plan_windows(10, 4, &config(TruncationStrategy::Tail, 0));
+        assert_eq!(tail, vec![vec![6..10]]);
+
+        let (both, truncated) = plan_windows(10, 5, &config(TruncationStrategy::HeadTail, 0));
+        assert_eq!(both, vec![vec![0..3, 8..10]]);
+        assert_eq!(truncated, 5);
+    }
+
+    #[test]
+    fn test_sliding_windows_cover_input() {
+        let (windows, truncated) = plan_windows(10, 4, &config(TruncationStrategy::SlidingWindow, 1));
+        assert_eq!(windows, vec![vec![0..4], vec![3..7], vec![6..10]]);
+        assert_eq!(truncated, 0);
+    }
+
+    #[test]
+    fn test_sliding_windows_respect_max_windows() {
+        let mut cfg = config(TruncationStrategy::SlidingWindow, 0);
+        cfg.max_windows = 2;
+        let (windows, truncated) = plan_windows(10, 3, &cfg);
+        assert_eq!(windows, vec![vec![0..3], vec![3..6]]);
+        assert_eq!(truncated, 4);
+    }
+
+    #[test]
+    fn test_truncate_text_keeps_utf8_boundaries() {
+        let tokenizer = CustomTokenizer::new();
+        let cfg = config(TruncationStrategy::Head, 0);
+        let result = truncate_text("çok güzel ürün ama kargo geç geldi", &tokenizer, 3, &cfg).unwrap();
+        assert_eq!(result.windows[0].text, "çok güzel ürün");
+        assert_eq!(result.total_tokens, 7);
+        assert_eq!(result.truncated_tokens, 4);
+    }
+}
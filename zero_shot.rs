@@ -0,0 +1,153 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::InferenceError;
+
+pub const DEFAULT_HYPOTHESIS_TEMPLATE: &str = "This example is {}.";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LabelScore {
+    pub label: String,
+    pub score: f32,
+}
+
+/// Where the entailment and contradiction classes sit in an NLI model's
+/// output, found from `id2label`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NliLabels {
+    pub entailment: usize,
+    pub contradiction: usize,
+}
+
+impl NliLabels {
+    pub fn from_labels(labels: &[String]) -> Result<Self> {
+        let find = |prefix: &str| {
+            labels
+                .iter()
+                .position(|label| label.to_lowercase().starts_with(prefix))
+        };
+
+        match (find("entail"), find("contra")) {
+            (Some(entailment), Some(contradiction)) => Ok(Self { entailment, contradiction }),
+            _ => Err(InferenceError::InvalidInput(format!(
+                "Model labels {:?} have no entailment/contradiction classes; zero-shot needs an NLI model",
+                labels
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Fills `{}` in the template with each candidate label. At most
+/// `max_labels` labels are accepted.
+pub fn hypotheses(template: &str, candidate_labels: &[String], max_labels: usize) -> Result<Vec<String>> {
+    if !template.contains("{}") {
+        return Err(InferenceError::InvalidInput(
+            "hypothesis_template must contain '{}' where the label goes".to_string(),
+        )
+        .into());
+    }
+    if candidate_labels.is_empty() {
+        return Err(InferenceError::InvalidInput("candidate_labels must not be empty".to_string()).into());
+    }
+    if candidate_labels.len() > max_labels {
+        return Err(InferenceError::InvalidInput(format!(
+            "Too many candidate_labels: {}, the limit is {}",
+            candidate_labels.len(),
+            max_labels
+        ))
+        .into());
+    }
+
+    Ok(candidate_labels
+        .iter()
+        .map(|label| template.replacen("{}", label, 1))
+        .collect())
+}
+
+/// Turns NLI logits (one row per candidate label) into label scores, best
+/// first. Single-label: softmax of the entailment logits across labels, so
+/// scores sum to 1. Multi-label: each label independently, softmax over its
+/// own contradiction/entailment pair.
+pub fn score_labels(
+    candidate_labels: &[String],
+    logits: &[Vec<f32>],
+    nli: NliLabels,
+    multi_label: bool,
+) -> Vec<LabelScore> {
+    let scores: Vec<f32> = if multi_label {
+        logits
+            .iter()
+            .map(|row| softmax(&[row[nli.contradiction], row[nli.entailment]])[1])
+            .collect()
+    } else {
+        softmax(&logits.iter().map(|row| row[nli.entailment]).collect::<Vec<_>>())
+    };
+
+    let mut scored: Vec<LabelScore> = candidate_labels
+        .iter()
+        .zip(scores)
+        .map(|(label, score)| LabelScore { label: label.clone(), score })
+        .collect();
+    scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+    scored
+}
+
+fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.into_iter().map(|exp| exp / sum).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    // roberta-large-mnli order
+    const NLI: NliLabels = NliLabels { contradiction: 0, entailment: 2 };
+
+    #[test]
+    fn test_nli_labels_from_id2label() {
+        let labels = strings(&["CONTRADICTION", "NEUTRAL", "ENTAILMENT"]);
+        assert_eq!(NliLabels::from_labels(&labels).unwrap(), NLI);
+        assert!(NliLabels::from_labels(&strings(&["NEGATIVE", "POSITIVE"])).is_err());
+    }
+
+    #[test]
+    fn test_hypotheses_fill_template() {
+        let labels = strings(&["billing", "shipping"]);
+        assert_eq!(
+            hypotheses("This ticket is about {}.", &labels, 2).unwrap(),
+            strings(&["This ticket is about billing.", "This ticket is about shipping."])
+        );
+        assert!(hypotheses("No placeholder", &labels, 2).is_err());
+        assert!(hypotheses(DEFAULT_HYPOTHESIS_TEMPLATE, &[], 2).is_err());
+        assert!(hypotheses(DEFAULT_HYPOTHESIS_TEMPLATE, &labels, 1).is_err());
+    }
+
+    #[test]
+    fn test_single_label_scores_sum_to_one() {
+        let labels = strings(&["billing", "shipping"]);
+        let logits = vec![vec![2.0, 0.0, 1.0], vec![-1.0, 0.0, 3.0]];
+
+        let scores = score_labels(&labels, &logits, NLI, false);
+        assert_eq!(scores[0].label, "shipping");
+        assert!((scores.iter().map(|s| s.score).sum::<f32>() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_multi_label_scores_are_independent() {
+        let labels = strings(&["billing", "shipping"]);
+        // Both hypotheses entailed
+        let logits = vec![vec![-3.0, 0.0, 3.0], vec![-2.0, 0.0, 2.0]];
+
+        let scores = score_labels(&labels, &logits, NLI, true);
+        assert!(scores.iter().all(|s| s.score > 0.9));
+        assert_eq!(scores[0].label, "billing");
+    }
+}